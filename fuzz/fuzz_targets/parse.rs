@@ -0,0 +1,15 @@
+// Tokenizes and parses arbitrary input. Every input has to either parse
+// or give an error that can be rendered, anything else is a bug.
+//
+// cargo +nightly fuzz run parse
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        if let Err(error) = kody::check_syntax(source) {
+            error.render(source, "fuzz.kd");
+        }
+    }
+});
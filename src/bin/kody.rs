@@ -1,12 +1,24 @@
 use std::env::args_os;
+use std::io::{stdin, stdout};
 use std::path::PathBuf;
 
-use kody::{run, Arguments, SourceType};
+use kody::{read_source, run, run_repl, Arguments, Backend, SourceType};
 
 fn main() {
+    // without a source file, or with kody repl, start an interactive session
+    let first_argument = args_os().nth(1);
+    if first_argument.is_none() || first_argument.as_deref() == Some("repl".as_ref()) {
+        if let Err(error) = run_repl(stdin().lock(), stdout()) {
+            handle_error(format!("error: {}\n", error));
+        }
+        return;
+    }
+
     let arguments = parse_args().unwrap_or_else(|e| handle_error(e));
-    if let Err(error) = run(&arguments) {
-        handle_error(error);
+    let source = read_source(&arguments)
+        .unwrap_or_else(|error| handle_error(error.render("", &arguments.source.name())));
+    if let Err(error) = run(&source, &arguments) {
+        handle_error(error.render(&source, &arguments.source.name()));
     }
 }
 
@@ -17,7 +29,7 @@ fn parse_args() -> Result<Arguments, String> {
         Some(val) => SourceType::File(PathBuf::from(val)),
         None => {
             return Err(String::from(
-                "Please provide a source file as a program argument!",
+                "error: Please provide a source file as a program argument!\n",
             ));
         }
     };
@@ -31,14 +43,24 @@ fn parse_args() -> Result<Arguments, String> {
         .iter()
         .any(|opt| opt == "--ignore-extensions" || opt == "-e");
 
+    let backend = if options
+        .iter()
+        .any(|opt| opt == "--tree-walker" || opt == "-t")
+    {
+        Backend::TreeWalker
+    } else {
+        Backend::Bytecode
+    };
+
     Ok(Arguments {
         source,
         is_verbose,
         ignore_extensions,
+        backend,
     })
 }
 
 fn handle_error(value: String) -> ! {
-    println!("ERROR: {}", value);
+    eprint!("{}", value);
     std::process::exit(1);
 }
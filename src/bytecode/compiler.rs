@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use super::{Capture, Chunk, Condition, Instruction, Program};
+use crate::libkody::get_global;
+use crate::runtime::objects::KodyObject;
+use crate::span::Span;
+use crate::syntax_tree::{KodyFunctionData, KodyNode, KodySyntaxTree};
+
+pub fn compile(syntax_tree: &KodySyntaxTree) -> Program {
+    let mut compiler = FunctionCompiler::new(None);
+    compiler.compile_statement(&syntax_tree.main);
+    Program {
+        main: Rc::new(compiler.finish(syntax_tree.main.span())),
+    }
+}
+
+fn compile_function(
+    func_data: &KodyFunctionData,
+    enclosing: &mut dyn Enclosing,
+    scopes: Vec<usize>,
+) -> Chunk {
+    let mut compiler = FunctionCompiler::new(Some((enclosing, scopes)));
+    for argument in &func_data.arguments {
+        compiler.declare(argument);
+    }
+    compiler.chunk.argument_count = func_data.arguments.len();
+    compiler.compile_statement(&func_data.body);
+    compiler.finish(func_data.body.span())
+}
+
+// What a function needs from the function it is defined in
+// to find the variables it captures
+trait Enclosing {
+    // looks for a variable in the given scopes and then
+    // in the variables this function can capture itself
+    fn capture(&mut self, scopes: &[usize], name: &str) -> Option<Capture>;
+}
+
+struct FunctionCompiler<'a> {
+    chunk: Chunk,
+    // the function this one is defined in,
+    // with the scopes that were open at the definition
+    enclosing: Option<(&'a mut dyn Enclosing, Vec<usize>)>,
+    // the local variables of every code block, including the ended ones,
+    // so that functions can capture variables assigned after their definition
+    scopes: Vec<Scope>,
+    // the code blocks that are being compiled
+    open_scopes: Vec<usize>,
+    // every variable gets its own slot so that a captured slot
+    // is never used by another variable
+    next_slot: usize,
+    // the functions defined in this one with the scopes open at their definition,
+    // they are compiled when this one is done
+    pending: Vec<(Rc<KodyFunctionData>, Vec<usize>)>,
+    // the values on the stack that belong to expressions being compiled,
+    // such as the function and the first arguments of a call
+    values: usize,
+    // the loops that are being compiled, the innermost one last
+    loops: Vec<Loop>,
+    // the index of every name in the names of the chunk
+    name_indices: HashMap<String, usize>,
+}
+
+#[derive(Default)]
+struct Scope {
+    // the slots of the variables in the order they were declared
+    slots: Vec<usize>,
+    // the slot of every name, a name declared again gets the newer slot
+    names: HashMap<String, usize>,
+}
+
+struct Loop {
+    // where continue jumps to
+    start: usize,
+    // the jumps of the breaks, they go to the end of the loop
+    breaks: Vec<usize>,
+    // the open code blocks and the values on the stack when the loop started,
+    // break and continue clean up everything that came after
+    open_scopes: usize,
+    values: usize,
+    // the values of the loop itself, such as the iterator of a for loop,
+    // only break removes them
+    loop_values: usize,
+}
+
+impl Enclosing for FunctionCompiler<'_> {
+    fn capture(&mut self, scopes: &[usize], name: &str) -> Option<Capture> {
+        match self.find_local(scopes, name) {
+            Some(slot) => Some(Capture::Local(slot)),
+            None => self.resolve_captured(name).map(Capture::Captured),
+        }
+    }
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(enclosing: Option<(&'a mut dyn Enclosing, Vec<usize>)>) -> FunctionCompiler<'a> {
+        FunctionCompiler {
+            chunk: Chunk::default(),
+            enclosing,
+            scopes: vec![Scope::default()],
+            open_scopes: vec![0],
+            next_slot: 0,
+            pending: vec![],
+            values: 0,
+            loops: vec![],
+            name_indices: HashMap::new(),
+        }
+    }
+
+    fn finish(mut self, span: Span) -> Chunk {
+        self.emit(Instruction::Empty, span);
+        self.emit(Instruction::Return, span);
+        for (func_data, scopes) in mem::take(&mut self.pending) {
+            let chunk = compile_function(&func_data, &mut self, scopes);
+            self.chunk.functions.push((func_data, Rc::new(chunk)));
+        }
+        self.chunk
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.chunk.instructions.push(instruction);
+        self.chunk.spans.push(span);
+        self.chunk.instructions.len() - 1
+    }
+
+    fn add_constant(&mut self, value: KodyObject) -> usize {
+        self.chunk.constants.push(value);
+        self.chunk.constants.len() - 1
+    }
+
+    fn add_name(&mut self, name: &str) -> usize {
+        if let Some(index) = self.name_indices.get(name) {
+            return *index;
+        }
+        self.chunk.names.push(name.to_string());
+        let index = self.chunk.names.len() - 1;
+        self.name_indices.insert(name.to_string(), index);
+        index
+    }
+
+    fn find_local(&self, scopes: &[usize], name: &str) -> Option<usize> {
+        scopes
+            .iter()
+            .rev()
+            .find_map(|scope| self.scopes[*scope].names.get(name).copied())
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.find_local(&self.open_scopes, name)
+    }
+
+    // the index of a variable from the code around the function definition
+    fn resolve_captured(&mut self, name: &str) -> Option<usize> {
+        let (enclosing, scopes) = self.enclosing.as_mut()?;
+        let capture = enclosing.capture(scopes, name)?;
+        let captures = &mut self.chunk.captures;
+        Some(match captures.iter().position(|other| *other == capture) {
+            Some(index) => index,
+            None => {
+                captures.push(capture);
+                captures.len() - 1
+            }
+        })
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.chunk.slot_count = self.next_slot;
+        // there is always at least the scope of the function itself
+        let scope = &mut self.scopes[*self.open_scopes.last().unwrap()];
+        scope.slots.push(slot);
+        scope.names.insert(name.to_string(), slot);
+        slot
+    }
+
+    fn begin_scope(&mut self) {
+        self.open_scopes.push(self.scopes.len());
+        self.scopes.push(Scope::default());
+    }
+
+    fn end_scope(&mut self, span: Span) {
+        if let Some(scope) = self.open_scopes.pop() {
+            for index in 0..self.scopes[scope].slots.len() {
+                let slot = self.scopes[scope].slots[index];
+                self.emit(Instruction::ClearLocal(slot), span);
+            }
+        }
+    }
+
+    // Leaves the code blocks and the values of the innermost loop and
+    // jumps to target, a break also leaves the values of the loop itself.
+    // Returns the index of the jump, or None outside of a loop, which the
+    // parser doesn't allow.
+    fn jump_out_of_loop(&mut self, target: usize, span: Span, is_break: bool) -> Option<usize> {
+        let inner_loop = self.loops.last()?;
+        let open_scopes = inner_loop.open_scopes;
+        let values = if is_break {
+            inner_loop.values
+        } else {
+            inner_loop.values + inner_loop.loop_values
+        };
+        for _ in values..self.values {
+            self.emit(Instruction::Pop, span);
+        }
+        for index in open_scopes..self.open_scopes.len() {
+            let scope = self.open_scopes[index];
+            for variable in 0..self.scopes[scope].slots.len() {
+                let slot = self.scopes[scope].slots[variable];
+                self.emit(Instruction::ClearLocal(slot), span);
+            }
+        }
+        Some(self.emit(Instruction::Jump(target), span))
+    }
+
+    // compiles a node whose value is not needed
+    fn compile_statement(&mut self, node: &KodyNode) {
+        self.compile_node(node);
+        // pushing an empty value and popping it right away does nothing
+        if self.chunk.instructions.last() == Some(&Instruction::Empty) {
+            self.chunk.instructions.pop();
+            self.chunk.spans.pop();
+        } else {
+            self.emit(Instruction::Pop, node.span());
+        }
+    }
+
+    fn compile_node(&mut self, node: &KodyNode) {
+        match node {
+            KodyNode::CodeBlock { statements, span } => {
+                self.begin_scope();
+                // function definitions are hoisted to the start of the block
+                for statement in statements {
+                    if let KodyNode::FunctionDefinition { function, span } = statement {
+                        let slot = self.declare(&function.name);
+                        self.compile_closure(function, *span);
+                        self.emit(Instruction::SetLocal(slot), *span);
+                    }
+                }
+                for statement in statements {
+                    self.compile_statement(statement);
+                }
+                self.end_scope(*span);
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::GetConstant { value, span } => {
+                let index = self.add_constant(value.clone());
+                self.emit(Instruction::Constant(index), *span);
+            }
+            KodyNode::GetVariable { name, span } => self.compile_get_variable(name, *span),
+            KodyNode::SetVariable { name, value, span } => {
+                self.compile_node(value);
+                let instruction = if let Some(slot) = self.resolve_local(name) {
+                    Instruction::SetLocal(slot)
+                } else if let Some(index) = self.resolve_captured(name) {
+                    Instruction::SetCaptured(index)
+                } else {
+                    Instruction::SetLocal(self.declare(name))
+                };
+                self.emit(instruction, *span);
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::IfStatement {
+                branches,
+                else_action,
+                span,
+            } => {
+                // every branch but the last one that runs jumps to the end
+                let mut jumps_to_end = vec![];
+                for (i, branch) in branches.iter().enumerate() {
+                    self.compile_node(&branch.condition);
+                    let jump_to_next = self.emit(
+                        Instruction::JumpIfFalse {
+                            target: 0,
+                            condition: Condition::If,
+                        },
+                        branch.condition.span(),
+                    );
+                    self.compile_statement(&branch.action);
+                    if i + 1 < branches.len() || else_action.is_some() {
+                        jumps_to_end.push(self.emit(Instruction::Jump(0), *span));
+                    }
+                    self.patch_jump(jump_to_next);
+                }
+                if let Some(else_action) = else_action {
+                    self.compile_statement(else_action);
+                }
+                for jump in jumps_to_end {
+                    self.patch_jump(jump);
+                }
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::WhileStatement {
+                condition,
+                action,
+                span,
+            } => {
+                let loop_start = self.chunk.instructions.len();
+                self.compile_node(condition);
+                let jump_to_end = self.emit(
+                    Instruction::JumpIfFalse {
+                        target: 0,
+                        condition: Condition::While,
+                    },
+                    condition.span(),
+                );
+                self.loops.push(Loop {
+                    start: loop_start,
+                    breaks: vec![],
+                    open_scopes: self.open_scopes.len(),
+                    values: self.values,
+                    loop_values: 0,
+                });
+                self.compile_statement(action);
+                self.emit(Instruction::Jump(loop_start), *span);
+                self.patch_jump(jump_to_end);
+                if let Some(finished_loop) = self.loops.pop() {
+                    for jump in finished_loop.breaks {
+                        self.patch_jump(jump);
+                    }
+                }
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::ForStatement {
+                variable,
+                iterable,
+                action,
+                span,
+            } => {
+                self.compile_node(iterable);
+                self.emit(Instruction::Iterate, iterable.span());
+                // break and continue also leave the scope of the variable
+                let outer_scopes = self.open_scopes.len();
+                let outer_values = self.values;
+                self.values += 1;
+                self.begin_scope();
+                let slot = self.declare(variable);
+
+                let loop_start = self.emit(Instruction::ForNext(0), iterable.span());
+                self.emit(Instruction::SetLocal(slot), *span);
+                self.loops.push(Loop {
+                    start: loop_start,
+                    breaks: vec![],
+                    open_scopes: outer_scopes,
+                    values: outer_values,
+                    loop_values: 1,
+                });
+                self.compile_statement(action);
+                // every iteration has its own variable
+                self.emit(Instruction::ClearLocal(slot), *span);
+                self.emit(Instruction::Jump(loop_start), *span);
+                self.patch_jump(loop_start);
+                if let Some(finished_loop) = self.loops.pop() {
+                    for jump in finished_loop.breaks {
+                        self.patch_jump(jump);
+                    }
+                }
+                self.end_scope(*span);
+                self.values -= 1;
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::Break { span } => {
+                if let Some(jump) = self.jump_out_of_loop(0, *span, true) {
+                    if let Some(inner_loop) = self.loops.last_mut() {
+                        inner_loop.breaks.push(jump);
+                    }
+                }
+                // like after a return the code after it expects a value
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::Continue { span } => {
+                if let Some(start) = self.loops.last().map(|inner_loop| inner_loop.start) {
+                    self.jump_out_of_loop(start, *span, false);
+                }
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::ReturnFromFunction { return_value, span } => {
+                self.compile_node(return_value);
+                self.emit(Instruction::Return, *span);
+                // the return never finishes but the code after it expects a value
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::CallFunction {
+                function,
+                arguments,
+                span,
+            } => {
+                self.compile_node(function);
+                self.values += 1;
+                for argument in arguments {
+                    self.compile_node(argument);
+                    self.values += 1;
+                }
+                self.values -= arguments.len() + 1;
+                self.emit(Instruction::Call(arguments.len()), *span);
+            }
+            KodyNode::OperatorChain {
+                first, operations, ..
+            } => {
+                self.compile_node(first);
+                for operation in operations {
+                    // the function goes below the value so far, like in a call
+                    self.values += 1;
+                    self.compile_get_variable(operation.operator, operation.span);
+                    self.emit(Instruction::Swap, operation.span);
+                    self.values += 1;
+                    self.compile_node(&operation.operand);
+                    self.values -= 2;
+                    self.emit(Instruction::Call(2), operation.span);
+                }
+            }
+            KodyNode::GetMember { base, member, span } => {
+                self.compile_node(base);
+                let member = self.add_name(member);
+                self.emit(Instruction::GetMember(member), *span);
+            }
+            KodyNode::SetMember {
+                base,
+                member,
+                operation,
+                value,
+                span,
+            } => {
+                self.compile_node(base);
+                self.values += 1;
+                self.compile_node(value);
+                self.values -= 1;
+                let member = self.add_name(member);
+                let operation = operation.map(|operation| self.add_name(operation));
+                self.emit(Instruction::SetMember { member, operation }, *span);
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::GetIndex { base, index, span } => {
+                self.compile_node(base);
+                self.values += 1;
+                self.compile_node(index);
+                self.values -= 1;
+                self.emit(Instruction::GetIndex, *span);
+            }
+            KodyNode::SetIndex {
+                base,
+                index,
+                value,
+                span,
+            } => {
+                self.compile_node(base);
+                self.values += 1;
+                self.compile_node(index);
+                self.values += 1;
+                self.compile_node(value);
+                self.values -= 2;
+                self.emit(Instruction::SetIndex, *span);
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::UpdateIndex {
+                base,
+                index,
+                operation,
+                value,
+                span,
+            } => {
+                self.compile_node(base);
+                self.values += 1;
+                self.compile_node(index);
+                self.values += 1;
+                self.compile_node(value);
+                self.values -= 2;
+                let operation = self.add_name(operation);
+                self.emit(Instruction::UpdateIndex(operation), *span);
+                self.emit(Instruction::Empty, *span);
+            }
+            // the code block around the definition has already created the function
+            KodyNode::FunctionDefinition { span, .. } => {
+                self.emit(Instruction::Empty, *span);
+            }
+            KodyNode::Lambda { function, span } => self.compile_closure(function, *span),
+        }
+    }
+
+    fn compile_get_variable(&mut self, name: &str, span: Span) {
+        let instruction = if let Some(slot) = self.resolve_local(name) {
+            Instruction::GetLocal {
+                slot,
+                name: self.add_name(name),
+            }
+        } else if let Some(index) = self.resolve_captured(name) {
+            Instruction::GetCaptured {
+                index,
+                name: self.add_name(name),
+            }
+        } else if let Some(value) = get_global(name) {
+            Instruction::Constant(self.add_constant(value))
+        } else {
+            Instruction::UndefinedVariable(self.add_name(name))
+        };
+        self.emit(instruction, span);
+    }
+
+    // the function is compiled later, when all the variables
+    // it can capture are known
+    fn compile_closure(&mut self, function: &Rc<KodyFunctionData>, span: Span) {
+        self.pending
+            .push((function.clone(), self.open_scopes.clone()));
+        self.emit(Instruction::Closure(self.pending.len() - 1), span);
+    }
+
+    // make a jump point to the next instruction
+    fn patch_jump(&mut self, index: usize) {
+        let next = self.chunk.instructions.len();
+        match &mut self.chunk.instructions[index] {
+            Instruction::Jump(target)
+            | Instruction::JumpIfFalse { target, .. }
+            | Instruction::ForNext(target) => *target = next,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// A compact instruction set that the syntax tree gets compiled into.
+//
+// Every expression leaves exactly one value on the stack. Statements that have
+// no value of their own (assignments, blocks, loops, ifs) push an empty value
+// that the enclosing code block pops away again.
+
+mod compiler;
+mod vm;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::runtime::objects::KodyObject;
+use crate::span::Span;
+use crate::syntax_tree::KodyFunctionData;
+
+pub use compiler::compile;
+pub use vm::run;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // push a value from the constant table of the chunk
+    Constant(usize),
+    // push an empty value
+    Empty,
+    Pop,
+    // swap the two values on top of the stack
+    Swap,
+    // read a local variable, name is only used for the error message
+    // when the variable hasn't been set yet
+    GetLocal {
+        slot: usize,
+        name: usize,
+    },
+    // pop the top of the stack into a local variable
+    SetLocal(usize),
+    // like GetLocal and SetLocal for the variables the running
+    // function captured from the code around its definition
+    GetCaptured {
+        index: usize,
+        name: usize,
+    },
+    SetCaptured(usize),
+    // a variable that doesn't exist, fails when executed
+    UndefinedVariable(usize),
+    // forget a local variable of a code block that has ended
+    ClearLocal(usize),
+    // push a new function made from the function of the chunk with the
+    // given index and the variables it captures
+    Closure(usize),
+    Jump(usize),
+    // pop a bool and jump if it is false
+    JumpIfFalse {
+        target: usize,
+        condition: Condition,
+    },
+    // pop a value and push an iterator over it
+    Iterate,
+    // push the next item of the iterator on top of the stack, or pop
+    // the iterator and jump to target when it has no items left
+    ForNext(usize),
+    // call the function below the given number of arguments
+    Call(usize),
+    // pop an object and push its member of the given name
+    GetMember(usize),
+    // pop a value and an object and set the member, combining the old
+    // member and the value with the native function operation if there is one
+    SetMember {
+        member: usize,
+        operation: Option<usize>,
+    },
+    // pop an index and a list and push the item at the index
+    GetIndex,
+    // pop a value, an index and a list and set the item at the index
+    SetIndex,
+    // like SetIndex but combine the old item and the value
+    // with the native function of the given name
+    UpdateIndex(usize),
+    Return,
+}
+
+// the statement a condition belongs to, for error messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    If,
+    While,
+}
+
+// where a function gets a captured variable from when it is created
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    // a local variable of the function that creates it
+    Local(usize),
+    // a variable that the creating function has captured itself
+    Captured(usize),
+}
+
+// a variable that is shared between a function call and the functions
+// that captured it, None until it has been set
+pub type SharedVariable = Rc<RefCell<Option<KodyObject>>>;
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub instructions: Vec<Instruction>,
+    // the source location of every instruction
+    pub spans: Vec<Span>,
+    pub constants: Vec<KodyObject>,
+    pub names: Vec<String>,
+    pub argument_count: usize,
+    // the number of local variable slots a call of the chunk needs
+    pub slot_count: usize,
+    // the variables a function made from this chunk captures
+    pub captures: Vec<Capture>,
+    // the functions defined in this chunk
+    pub functions: Vec<(Rc<KodyFunctionData>, Rc<Chunk>)>,
+}
+
+#[derive(Debug)]
+pub struct Program {
+    pub main: Rc<Chunk>,
+}
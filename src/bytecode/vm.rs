@@ -0,0 +1,618 @@
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+
+use super::{Capture, Chunk, Condition, Instruction, Program, SharedVariable};
+use crate::error::KodyError;
+use crate::libkody::{get_global, get_index, get_member, iterate, set_index, Context};
+use crate::runtime::objects::{Captured, KodyFunction, KodyObject, KodyValue, NativeFunction};
+use crate::runtime::{
+    arity_error, assign_member, foreign_function_error, index_error, iteration_error, member_error,
+    native_error, next_item, not_callable_error, recursion_error, update_index, with_stack,
+    write_output, Output, MAX_CALL_DEPTH,
+};
+use crate::span::Span;
+
+pub fn run(program: &Program, output: Output) -> Result<KodyObject, KodyError> {
+    let mut vm = Vm {
+        stack: vec![],
+        slots: vec![],
+        frames: vec![],
+        empty: KodyObject::new(),
+        native_span: Span::default(),
+        call_error: None,
+        native_calls: 0,
+        output,
+    };
+    vm.slots.resize(program.main.slot_count, Slot::Value(None));
+    vm.execute(program.main.clone(), Rc::new(vec![]), 0)
+}
+
+// the state of a function that is waiting for a call to return
+struct Frame {
+    chunk: Rc<Chunk>,
+    variables: Rc<Vec<SharedVariable>>,
+    ip: usize,
+    slots_start: usize,
+    stack_start: usize,
+}
+
+// A local variable. It becomes shared when a function captures it,
+// so that the function and the call see the same value.
+#[derive(Clone)]
+enum Slot {
+    Value(Option<KodyObject>),
+    Shared(SharedVariable),
+}
+
+struct Vm {
+    stack: Vec<KodyObject>,
+    // the local variables of every function in the call stack
+    slots: Vec<Slot>,
+    frames: Vec<Frame>,
+    // shared so that pushing an empty value doesn't allocate
+    empty: KodyObject,
+    // the span of the running native function, for the errors of the calls it makes
+    native_span: Span,
+    // the error of a call made by a native function
+    call_error: Option<KodyError>,
+    // the functions called by native functions that are still running,
+    // each of them runs in an execute of its own
+    native_calls: usize,
+    output: Output,
+}
+
+impl Context for Vm {
+    fn call(
+        &mut self,
+        function: &KodyObject,
+        arguments: Vec<KodyObject>,
+    ) -> Result<KodyObject, String> {
+        self.call_value(function, arguments, self.native_span)
+            .map_err(|error| {
+                let message = error.message().to_string();
+                self.call_error = Some(error);
+                message
+            })
+    }
+
+    fn write_output(&mut self, text: &str) -> Result<(), String> {
+        write_output(&self.output, text)
+    }
+}
+
+fn function_parts(
+    function: &KodyFunction,
+    argument_count: usize,
+    span: Span,
+) -> Result<(Rc<Chunk>, Rc<Vec<SharedVariable>>), KodyError> {
+    if function.data.arguments.len() != argument_count {
+        return Err(arity_error(&function.data, argument_count, span));
+    }
+    match &function.captured {
+        Captured::Variables { chunk, variables } => Ok((chunk.clone(), variables.clone())),
+        Captured::Scope(_) => Err(foreign_function_error(span)),
+    }
+}
+
+impl Vm {
+    fn pop(&mut self) -> KodyObject {
+        // the compiler makes sure that there is always something to pop
+        self.stack.pop().unwrap_or_default()
+    }
+
+    fn share(&mut self, slot: usize) -> SharedVariable {
+        match &mut self.slots[slot] {
+            Slot::Shared(variable) => variable.clone(),
+            Slot::Value(value) => {
+                let variable = Rc::new(RefCell::new(value.take()));
+                self.slots[slot] = Slot::Shared(variable.clone());
+                variable
+            }
+        }
+    }
+
+    // calls a function for a native function and runs it to the end
+    fn call_value(
+        &mut self,
+        function: &KodyObject,
+        mut arguments: Vec<KodyObject>,
+        span: Span,
+    ) -> Result<KodyObject, KodyError> {
+        match function.value.as_ref() {
+            KodyValue::Function(function) => {
+                let (chunk, variables) = function_parts(function, arguments.len(), span)?;
+                self.check_depth(span)?;
+                let slots_start = self.slots.len();
+                self.slots
+                    .resize(slots_start + chunk.slot_count, Slot::Value(None));
+                for (slot, argument) in self.slots[slots_start..].iter_mut().zip(arguments) {
+                    *slot = Slot::Value(Some(argument));
+                }
+                self.native_calls += 1;
+                let result = with_stack(|| self.execute(chunk, variables, slots_start));
+                self.native_calls -= 1;
+                result
+            }
+            KodyValue::NativeFunction(function) => self.call_native(function, arguments, span),
+            KodyValue::Method { receiver, function } => {
+                arguments.insert(0, receiver.clone());
+                self.call_native(function, arguments, span)
+            }
+            _ => Err(not_callable_error(span)),
+        }
+    }
+
+    // A call fails when MAX_CALL_DEPTH functions are running already. Each
+    // frame belongs to a running function, the last one maybe to the main
+    // program, but then the function running now isn't counted.
+    fn check_depth(&self, span: Span) -> Result<(), KodyError> {
+        if self.frames.len() + self.native_calls >= MAX_CALL_DEPTH {
+            return Err(recursion_error(span));
+        }
+        Ok(())
+    }
+
+    fn call_native(
+        &mut self,
+        function: &NativeFunction,
+        arguments: Vec<KodyObject>,
+        span: Span,
+    ) -> Result<KodyObject, KodyError> {
+        let outer_span = mem::replace(&mut self.native_span, span);
+        let result = function.call(self, arguments);
+        self.native_span = outer_span;
+        result.map_err(|error| native_error(error, self.call_error.take(), span))
+    }
+
+    // Runs a chunk whose local variables start at slots_start until it returns.
+    // The functions it calls run in the same loop, only the ones called
+    // by native functions start a loop of their own.
+    fn execute(
+        &mut self,
+        mut chunk: Rc<Chunk>,
+        // the variables captured by the running function
+        mut variables: Rc<Vec<SharedVariable>>,
+        mut slots_start: usize,
+    ) -> Result<KodyObject, KodyError> {
+        let frames_start = self.frames.len();
+        let stack_start = self.stack.len();
+        let mut ip = 0;
+
+        loop {
+            let instruction = chunk.instructions[ip];
+            ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => self.stack.push(chunk.constants[index].clone()),
+                Instruction::Empty => self.stack.push(self.empty.clone()),
+                Instruction::Pop => {
+                    self.stack.pop();
+                }
+                Instruction::Swap => {
+                    let length = self.stack.len();
+                    self.stack.swap(length - 1, length - 2);
+                }
+                Instruction::GetLocal { slot, name } => {
+                    let value = match &self.slots[slots_start + slot] {
+                        Slot::Value(value) => value.clone(),
+                        Slot::Shared(variable) => variable.borrow().clone(),
+                    };
+                    match value {
+                        Some(value) => self.stack.push(value),
+                        None => self.stack.push(unset_variable(&chunk, name, ip - 1)?),
+                    }
+                }
+                Instruction::SetLocal(slot) => {
+                    let value = self.pop();
+                    match &mut self.slots[slots_start + slot] {
+                        Slot::Value(old_value) => *old_value = Some(value),
+                        Slot::Shared(variable) => *variable.borrow_mut() = Some(value),
+                    }
+                }
+                Instruction::GetCaptured { index, name } => match &*variables[index].borrow() {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => self.stack.push(unset_variable(&chunk, name, ip - 1)?),
+                },
+                Instruction::SetCaptured(index) => {
+                    let value = self.pop();
+                    *variables[index].borrow_mut() = Some(value);
+                }
+                Instruction::UndefinedVariable(name) => {
+                    return Err(undefined_variable(&chunk, name, ip - 1))
+                }
+                Instruction::ClearLocal(slot) => self.slots[slots_start + slot] = Slot::Value(None),
+                Instruction::Closure(index) => {
+                    let (func_data, function_chunk) = &chunk.functions[index];
+                    let captured = function_chunk
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(slot) => self.share(slots_start + slot),
+                            Capture::Captured(index) => variables[*index].clone(),
+                        })
+                        .collect();
+                    self.stack
+                        .push(KodyObject::from(KodyValue::Function(Rc::new(
+                            KodyFunction {
+                                data: func_data.clone(),
+                                captured: Captured::Variables {
+                                    chunk: function_chunk.clone(),
+                                    variables: Rc::new(captured),
+                                },
+                            },
+                        ))));
+                }
+                Instruction::Jump(target) => ip = target,
+                Instruction::JumpIfFalse { target, condition } => match self.pop().value.as_ref() {
+                    KodyValue::Bool(true) => (),
+                    KodyValue::Bool(false) => ip = target,
+                    _ => {
+                        let statement = match condition {
+                            Condition::If => "if",
+                            Condition::While => "while",
+                        };
+                        return Err(KodyError::runtime(
+                            "E0202",
+                            format!("Object in {} condition was not a bool!", statement),
+                            chunk.spans[ip - 1],
+                        ));
+                    }
+                },
+                Instruction::Iterate => {
+                    let value = self.pop();
+                    let iterator = iterate(&value)
+                        .map_err(|error| iteration_error(error, chunk.spans[ip - 1]))?;
+                    self.stack.push(iterator);
+                }
+                Instruction::ForNext(target) => {
+                    let span = chunk.spans[ip - 1];
+                    let iterator = self.stack.last().cloned().unwrap_or_default();
+                    let outer_span = mem::replace(&mut self.native_span, span);
+                    let item = next_item(&iterator, self);
+                    self.native_span = outer_span;
+                    match item.map_err(|error| native_error(error, self.call_error.take(), span))? {
+                        Some(item) => self.stack.push(item),
+                        None => {
+                            self.stack.pop();
+                            ip = target;
+                        }
+                    }
+                }
+                Instruction::Call(argument_count) => {
+                    let span = chunk.spans[ip - 1];
+                    let callee_index = self.stack.len() - argument_count - 1;
+                    let callee = self.stack[callee_index].clone();
+
+                    match callee.value.as_ref() {
+                        KodyValue::Function(function) => {
+                            let (function_chunk, function_variables) =
+                                function_parts(function, argument_count, span)?;
+                            self.check_depth(span)?;
+
+                            self.frames.push(Frame {
+                                chunk: mem::replace(&mut chunk, function_chunk),
+                                variables: mem::replace(&mut variables, function_variables),
+                                ip,
+                                slots_start,
+                                stack_start: callee_index,
+                            });
+                            ip = 0;
+                            slots_start = self.slots.len();
+                            self.slots
+                                .resize(slots_start + chunk.slot_count, Slot::Value(None));
+
+                            // the arguments are the first local variables
+                            for (slot, argument) in self.slots[slots_start..]
+                                .iter_mut()
+                                .zip(self.stack.drain(callee_index + 1..))
+                            {
+                                *slot = Slot::Value(Some(argument));
+                            }
+                            self.stack.truncate(callee_index);
+                        }
+                        KodyValue::NativeFunction(function) => {
+                            let arguments = self.stack.split_off(callee_index + 1);
+                            self.stack.truncate(callee_index);
+                            let result = self.call_native(function, arguments, span)?;
+                            self.stack.push(result);
+                        }
+                        KodyValue::Method { receiver, function } => {
+                            // the receiver takes the place of the method on the stack
+                            self.stack[callee_index] = receiver.clone();
+                            let arguments = self.stack.split_off(callee_index);
+                            let result = self.call_native(function, arguments, span)?;
+                            self.stack.push(result);
+                        }
+                        _ => return Err(not_callable_error(span)),
+                    }
+                }
+                Instruction::GetMember(member) => {
+                    let base = self.pop();
+                    let value = get_member(&base, &chunk.names[member])
+                        .map_err(|error| member_error(error, chunk.spans[ip - 1]))?;
+                    self.stack.push(value);
+                }
+                Instruction::SetMember { member, operation } => {
+                    let value = self.pop();
+                    let base = self.pop();
+                    assign_member(
+                        self,
+                        &base,
+                        &chunk.names[member],
+                        operation.map(|operation| chunk.names[operation].as_str()),
+                        value,
+                        chunk.spans[ip - 1],
+                    )?;
+                }
+                Instruction::GetIndex => {
+                    let index = self.pop();
+                    let base = self.pop();
+                    let item = get_index(&base, &index)
+                        .map_err(|error| index_error(error, chunk.spans[ip - 1]))?;
+                    self.stack.push(item);
+                }
+                Instruction::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let base = self.pop();
+                    set_index(&base, &index, value)
+                        .map_err(|error| index_error(error, chunk.spans[ip - 1]))?;
+                }
+                Instruction::UpdateIndex(operation) => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let base = self.pop();
+                    update_index(
+                        self,
+                        &base,
+                        &index,
+                        &chunk.names[operation],
+                        value,
+                        chunk.spans[ip - 1],
+                    )?;
+                }
+                Instruction::Return => {
+                    let value = self.pop();
+                    self.slots.truncate(slots_start);
+                    // the frames below frames_start belong to other loops
+                    let frame = if self.frames.len() > frames_start {
+                        self.frames.pop()
+                    } else {
+                        None
+                    };
+                    match frame {
+                        // the chunk this loop started with is done
+                        None => {
+                            self.stack.truncate(stack_start);
+                            return Ok(value);
+                        }
+                        Some(frame) => {
+                            self.stack.truncate(frame.stack_start);
+                            chunk = frame.chunk;
+                            variables = frame.variables;
+                            ip = frame.ip;
+                            slots_start = frame.slots_start;
+                            self.stack.push(value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A variable that hasn't been set yet, or whose code block has ended, is
+// the global of that name if there is one, like in the tree walker
+fn unset_variable(chunk: &Chunk, name: usize, ip: usize) -> Result<KodyObject, KodyError> {
+    get_global(&chunk.names[name]).ok_or_else(|| undefined_variable(chunk, name, ip))
+}
+
+fn undefined_variable(chunk: &Chunk, name: usize, ip: usize) -> KodyError {
+    KodyError::runtime(
+        "E0201",
+        format!(
+            "Variable name {} doesn't match any known variable!",
+            chunk.names[name]
+        ),
+        chunk.spans[ip],
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::bytecode::compile;
+    use crate::error::KodyError;
+    use crate::runtime::execute;
+    use crate::runtime::objects::{KodyNumber, KodyObject, KodyValue};
+    use crate::syntax_tree::parse_tokens;
+    use crate::tokenizer::tokenize;
+
+    // runs the source with both backends and checks that
+    // they agree on the result and on what gets printed
+    fn run_both(source: &str) -> Result<KodyObject, KodyError> {
+        run_both_with_output(source).0
+    }
+
+    fn run_both_with_output(source: &str) -> (Result<KodyObject, KodyError>, String) {
+        let syntax_tree = parse_tokens(&tokenize(source).unwrap()).unwrap();
+        let tree_walker_output = Rc::new(RefCell::new(vec![]));
+        let tree_walker = execute(&syntax_tree, tree_walker_output.clone());
+        let bytecode_output = Rc::new(RefCell::new(vec![]));
+        let bytecode = super::run(&compile(&syntax_tree), bytecode_output.clone());
+        assert_eq!(tree_walker, bytecode);
+        assert_eq!(tree_walker_output, bytecode_output);
+        let output = String::from_utf8(bytecode_output.take()).unwrap();
+        (bytecode, output)
+    }
+
+    fn number(x: i64) -> KodyObject {
+        KodyObject::from(KodyValue::Number(KodyNumber::from_int(x)))
+    }
+
+    #[test]
+    fn return_from_loop() {
+        let source = "
+            func find(n) {
+                i = 0
+                while i < 10 {
+                    if i == n { return i * 2 }
+                    i = i + 1
+                }
+                return 99
+            }
+            return find(3) + find(20)
+        ";
+        assert_eq!(run_both(source), Ok(number(105)));
+    }
+
+    #[test]
+    fn scopes() {
+        let source = "
+            x = 1
+            if true { x = 2 y = 3 }
+            return x
+        ";
+        assert_eq!(run_both(source), Ok(number(2)));
+
+        // variables of a code block are gone after it ends
+        let source = "
+            if true { y = 3 }
+            return y
+        ";
+        assert_eq!(run_both(source).unwrap_err().code(), "E0201");
+    }
+
+    #[test]
+    fn members() {
+        let source = "
+            point = {\"x\": 1}
+            point.x += 2
+            point.y = point.x * 2
+            xs = [point.x, point.y]
+            xs.push(point.len())
+            return xs.len() * 100 + xs[2] * 10 + \"abc\".len()
+        ";
+        assert_eq!(run_both(source), Ok(number(323)));
+        assert_eq!(run_both("return [].missing").unwrap_err().code(), "E0207");
+        assert_eq!(run_both("x = 1 x.y = 2").unwrap_err().code(), "E0207");
+    }
+
+    #[test]
+    fn recursion() {
+        let source = "
+            func fib(n) {
+                if n < 2 { return n }
+                return fib(n - 1) + fib(n - 2)
+            }
+            return fib(15)
+        ";
+        assert_eq!(run_both(source), Ok(number(610)));
+    }
+
+    #[test]
+    fn closures() {
+        let source = "
+            func make_counter() {
+                count = 0
+                func increment() {
+                    count += 1
+                    return count
+                }
+                return increment
+            }
+            a = make_counter()
+            b = make_counter()
+            a() a()
+            return a() * 10 + b()
+        ";
+        assert_eq!(run_both(source), Ok(number(31)));
+
+        let source = "
+            func make_adder(n) {
+                func add(x) { return x + n }
+                return add
+            }
+            add_two = make_adder(2)
+            return make_adder(10)(add_two(3))
+        ";
+        assert_eq!(run_both(source), Ok(number(15)));
+
+        // every iteration of a loop gets its own variables
+        let source = "
+            functions = []
+            i = 0
+            while i < 3 {
+                j = i
+                func get() { return j }
+                functions.push(get)
+                i += 1
+            }
+            return functions[0]() * 100 + functions[1]() * 10 + functions[2]()
+        ";
+        assert_eq!(run_both(source), Ok(number(12)));
+    }
+
+    #[test]
+    fn nested_functions() {
+        // functions can call each other before their definition
+        // and can use variables that are set later
+        let source = "
+            func is_even(n) { if n == 0 { return true } return is_odd(n - 1) }
+            func is_odd(n) { if n == 0 { return false } return is_even(n - 1) }
+            func scaled(x) { return x * factor }
+            factor = 3
+            if is_odd(7) { return scaled(5) }
+        ";
+        assert_eq!(run_both(source), Ok(number(15)));
+
+        // a function is only visible in the block it was defined in
+        let source = "
+            func outer() {
+                func inner() { return 1 }
+                return inner() + 1
+            }
+            outer()
+            return inner()
+        ";
+        assert_eq!(run_both(source).unwrap_err().code(), "E0201");
+    }
+
+    #[test]
+    fn lambdas() {
+        let source = "
+            offset = 1
+            xs = [3, 1, 2].map(func(x) { x * 10 + offset })
+            xs = filter(xs, func(x) x > 11)
+            xs.sort_by(func(x) -x)
+            return xs[0] * 100 + xs[1] + func(a, b) { a * b }(2, 3)
+        ";
+        assert_eq!(run_both(source), Ok(number(3127)));
+
+        // errors inside a callback are reported where they happen
+        let error = run_both("[1].map(func(x) missing)").unwrap_err();
+        assert_eq!(error.code(), "E0201");
+        assert_eq!(error.span().map(|span| span.column), Some(17));
+        assert_eq!(
+            run_both("[1].filter(func(x) 1)").unwrap_err().code(),
+            "E0205"
+        );
+        assert_eq!(run_both("[1].map(func() 1)").unwrap_err().code(), "E0203");
+    }
+
+    #[test]
+    fn output() {
+        let source = "
+            print(\"a\", 1)
+            xs = [2, 3] xs.map(print)
+            func f() { print() print([\"b\"]) }
+            f()
+        ";
+        let (result, output) = run_both_with_output(source);
+        assert_eq!(result, Ok(KodyObject::new()));
+        assert_eq!(output, "a1\n2\n3\n\n[\"b\"]\n");
+    }
+}
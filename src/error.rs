@@ -0,0 +1,254 @@
+use std::fmt;
+
+use crate::span::Span;
+
+// Error codes:
+//
+// E0001 - E0099: reading and tokenizing the source code
+// E0100 - E0199: parsing the tokens
+// E0200 - E0299: running the program
+//
+// | code  | error                                           |
+// |-------|-------------------------------------------------|
+// | E0001 | a character that doesn't start any token        |
+// | E0002 | a malformed number literal                      |
+// | E0003 | an invalid escape sequence in a string          |
+// | E0004 | an unclosed string literal                      |
+// | E0005 | a malformed format string                       |
+// | E0010 | the source file could not be read               |
+// | E0011 | the source file has the wrong extension         |
+// | E0101 | an unexpected token                             |
+// | E0102 | the code ended in the middle of an expression   |
+// | E0103 | an unclosed ( or {                              |
+// | E0104 | assignment to something other than a variable   |
+// | E0105 | a malformed function definition                 |
+// | E0106 | two consecutive + or - operators                |
+// | E0107 | a number literal that can't be read             |
+// | E0108 | a program with nothing but function definitions |
+// | E0109 | break or continue outside of a loop             |
+// | E0110 | a malformed for loop                            |
+// | E0111 | a malformed if, else if or else                 |
+// | E0112 | too deeply nested code or a too long expression |
+// | E0201 | use of an undefined variable                    |
+// | E0202 | a condition that is not a bool                  |
+// | E0203 | a call with the wrong number of arguments       |
+// | E0204 | a call of something that is not a function      |
+// | E0205 | an error returned by a native function          |
+// | E0206 | a bad index or key, or indexing something else  |
+// | E0207 | a missing member or a member that can't be set  |
+// | E0208 | a for loop over something that isn't iterable   |
+// | E0209 | too many nested function calls                  |
+
+// The stage of the interpreter the error came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum KodyError {
+    // the source code could not be read
+    Input(Diagnostic),
+    Lex(Diagnostic),
+    Parse(Diagnostic),
+    Runtime(Diagnostic),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    // a short code such as E0201 that identifies the kind of the error
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl KodyError {
+    pub fn input(code: &'static str, message: impl Into<String>) -> KodyError {
+        KodyError::Input(Diagnostic::new(code, message, None))
+    }
+
+    pub fn lex(code: &'static str, message: impl Into<String>, span: Span) -> KodyError {
+        KodyError::Lex(Diagnostic::new(code, message, Some(span)))
+    }
+
+    pub fn parse(code: &'static str, message: impl Into<String>, span: Span) -> KodyError {
+        KodyError::Parse(Diagnostic::new(code, message, Some(span)))
+    }
+
+    pub fn runtime(code: &'static str, message: impl Into<String>, span: Span) -> KodyError {
+        KodyError::Runtime(Diagnostic::new(code, message, Some(span)))
+    }
+
+    // an error that doesn't point to a place in the source code,
+    // such as one made by a host program
+    pub fn without_span(mut self) -> KodyError {
+        self.diagnostic_mut().span = None;
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> KodyError {
+        self.diagnostic_mut().notes.push(note.into());
+        self
+    }
+
+    pub fn diagnostic(&self) -> &Diagnostic {
+        match self {
+            KodyError::Input(diagnostic)
+            | KodyError::Lex(diagnostic)
+            | KodyError::Parse(diagnostic)
+            | KodyError::Runtime(diagnostic) => diagnostic,
+        }
+    }
+
+    fn diagnostic_mut(&mut self) -> &mut Diagnostic {
+        match self {
+            KodyError::Input(diagnostic)
+            | KodyError::Lex(diagnostic)
+            | KodyError::Parse(diagnostic)
+            | KodyError::Runtime(diagnostic) => diagnostic,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.diagnostic().code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.diagnostic().message
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.diagnostic().span
+    }
+
+    // Renders the error like rustc does, with the offending source line
+    // and a caret underline below the primary span:
+    //
+    // error[E0201]: Variable name z doesn't match any known variable!
+    //  --> example.kd:2:9
+    //   |
+    // 2 | y = x + z
+    //   |         ^
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let diagnostic = self.diagnostic();
+        let mut output = format!("error[{}]: {}\n", diagnostic.code, diagnostic.message);
+
+        let span = match diagnostic.span {
+            Some(span) => span,
+            None => {
+                for note in &diagnostic.notes {
+                    output.push_str(&format!("  = note: {}\n", note));
+                }
+                return output;
+            }
+        };
+
+        let line_number = span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let line = source
+            .lines()
+            .nth(span.line.saturating_sub(1))
+            .unwrap_or("");
+
+        // the part of the line before the span keeps its tabs so that the caret lines up
+        let line_start = source[..span.start.min(source.len())]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let padding: String = source[line_start..span.start.min(source.len())]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // a span that continues on the next lines is underlined until the end of its first line
+        let underline_end = span.end.min(line_start + line.len()).max(span.start);
+        let underline_len = source
+            .get(span.start..underline_end)
+            .map_or(0, |text| text.chars().count())
+            .max(1);
+
+        output.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter, file_name, span.line, span.column
+        ));
+        output.push_str(&format!("{} |\n", gutter));
+        output.push_str(&format!("{} | {}\n", line_number, line));
+        output.push_str(&format!(
+            "{} | {}{}\n",
+            gutter,
+            padding,
+            "^".repeat(underline_len)
+        ));
+        for note in &diagnostic.notes {
+            output.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+
+        output
+    }
+}
+
+impl Diagnostic {
+    fn new(code: &'static str, message: impl Into<String>, span: Option<Span>) -> Diagnostic {
+        Diagnostic {
+            code,
+            message: message.into(),
+            span,
+            notes: vec![],
+        }
+    }
+}
+
+impl fmt::Display for KodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let diagnostic = self.diagnostic();
+        match diagnostic.span {
+            Some(span) => write!(
+                f,
+                "error[{}] at {}: {}",
+                diagnostic.code, span, diagnostic.message
+            ),
+            None => write!(f, "error[{}]: {}", diagnostic.code, diagnostic.message),
+        }
+    }
+}
+
+impl std::error::Error for KodyError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn render() {
+        let source = "x = 1\n\ty = x +& 2\n";
+        let error = tokenize(source).unwrap_err();
+        assert!(matches!(error, KodyError::Lex(_)));
+        assert_eq!(
+            error.render(source, "test.kd"),
+            "error[E0001]: Could not match character '&' to any token\n \
+             --> test.kd:2:9\n  \
+             |\n\
+             2 | \ty = x +& 2\n  \
+             | \t       ^\n"
+        );
+    }
+
+    #[test]
+    fn render_notes() {
+        let error = KodyError::runtime(
+            "E0203",
+            "Wrong number of arguments!",
+            Span {
+                start: 0,
+                end: 6,
+                line: 1,
+                column: 1,
+            },
+        )
+        .with_note("f takes 2 arguments");
+        assert_eq!(
+            error.render("f(1)\n", "test.kd"),
+            "error[E0203]: Wrong number of arguments!\n \
+             --> test.kd:1:1\n  \
+             |\n\
+             1 | f(1)\n  \
+             | ^^^^\n  \
+             = note: f takes 2 arguments\n"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+// An interpreter for embedding kody in other programs. Everything runs in a
+// single session, so globals, functions and registered natives stay around
+// between calls.
+
+use std::rc::Rc;
+
+use crate::error::KodyError;
+use crate::libkody::Context;
+use crate::runtime::objects::{KodyObject, KodyValue, NativeFunction, TypedFunction};
+use crate::runtime::{Output, Session};
+use crate::span::Span;
+use crate::syntax_tree::{parse_input, KodyNode};
+use crate::tokenizer::tokenize;
+
+pub struct Interpreter {
+    session: Session,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter {
+            session: Session::new(),
+        }
+    }
+
+    // Makes a Rust function callable from kody code as a global function.
+    // The error messages it returns become E0205 runtime errors.
+    pub fn register_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(Vec<KodyObject>) -> Result<KodyObject, String> + 'static,
+    {
+        let function = NativeFunction::Host(Rc::new(
+            move |_: &mut dyn Context, arguments: Vec<KodyObject>| function(arguments),
+        ));
+        self.set_global(name, KodyObject::from(KodyValue::NativeFunction(function)));
+    }
+
+    // Like register_function, but the parameters and the result are Rust
+    // types such as i64, String or Vec<bool>. Calls with the wrong number
+    // or types of arguments fail before the function runs.
+    pub fn register_typed<F, Arguments>(&mut self, name: &str, function: F)
+    where
+        F: TypedFunction<Arguments> + 'static,
+    {
+        let function_name = String::from(name);
+        self.register_function(name, move |arguments| {
+            function.call_typed(&arguments, &function_name)
+        });
+    }
+
+    // Sends what the programs print to output instead of stdout.
+    // Keep a clone of it to read what was written.
+    pub fn set_output(&mut self, output: Output) {
+        self.session.set_output(output);
+    }
+
+    pub fn set_global(&mut self, name: &str, value: KodyObject) {
+        self.session.set_global(name, value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<KodyObject> {
+        self.session.get_global(name)
+    }
+
+    // Runs source code in the global scope. The result is the value
+    // it returns or else the value of its last statement.
+    pub fn eval(&mut self, source: &str) -> Result<KodyObject, KodyError> {
+        let tree = parse_input(&tokenize(source)?)?;
+        match &tree.main {
+            KodyNode::CodeBlock { statements, .. } => self.session.execute_statements(statements),
+            other => self.session.execute_statements(std::slice::from_ref(other)),
+        }
+    }
+
+    // Calls a global function, such as one defined by eval
+    pub fn call(
+        &mut self,
+        name: &str,
+        arguments: Vec<KodyObject>,
+    ) -> Result<KodyObject, KodyError> {
+        let function = self.get_global(name).ok_or_else(|| {
+            KodyError::runtime(
+                "E0201",
+                format!("Variable name {} doesn't match any known variable!", name),
+                Span::default(),
+            )
+            .without_span()
+        })?;
+        self.session.call(&function, arguments)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
@@ -1,8 +1,13 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod bytecode;
+mod error;
+mod interpreter;
 mod libkody;
+mod repl;
 mod runtime;
+mod span;
 mod syntax_tree;
 mod tokenizer;
 
@@ -12,29 +17,66 @@ use std::io::Read;
 use std::path::PathBuf;
 use std::time::Instant;
 
+pub use error::{Diagnostic, KodyError};
+pub use interpreter::Interpreter;
+pub use repl::run_repl;
+pub use runtime::objects::{
+    FromArguments, FromKody, IntoKody, IntoKodyResult, KodyMap, KodyNumber, KodyObject, KodyValue,
+    MapKey, TypedFunction,
+};
+pub use runtime::Output;
+pub use span::Span;
+
 use runtime::execute;
 use syntax_tree::{parse_tokens, KodySyntaxTree};
-use tokenizer::tokenize;
+use tokenizer::{tokenize, SpannedToken};
+
+pub fn read_source(arguments: &Arguments) -> Result<String, KodyError> {
+    match &arguments.source {
+        SourceType::File(path) => {
+            if path.extension() != Some(OsStr::new("kd")) && !arguments.ignore_extensions {
+                return Err(KodyError::input(
+                    "E0011",
+                    "Incorrect source file extension. Use .kd extension or the --ignore-extensions flag.",
+                ));
+            }
+            get_file_contents(path)
+        }
+        SourceType::Text(data) => Ok(data.clone()),
+    }
+}
+
+pub fn run(source: &str, arguments: &Arguments) -> Result<(), KodyError> {
+    run_with_output(source, arguments, runtime::standard_output())
+}
 
-pub fn run(arguments: &Arguments) -> Result<(), String> {
+// Runs a program with everything it prints written to output
+pub fn run_with_output(
+    source: &str,
+    arguments: &Arguments,
+    output: Output,
+) -> Result<(), KodyError> {
     let start_time = Instant::now();
 
-    if let SourceType::File(path) = &arguments.source {
-        if path.extension() != Some(OsStr::new("kd")) && !arguments.ignore_extensions {
-            return Err(String::from(
-            "Incorrect source file extension. Use .kd extension or the --ignore-extensions flag.",
-        ));
-        }
-    }
+    let tree = parse_file(source, arguments)?;
 
-    let source_data = match &arguments.source {
-        SourceType::File(path) => get_file_contents(&path)?,
-        SourceType::Text(data) => data.clone(),
-    };
+    match arguments.backend {
+        Backend::Bytecode => {
+            let program = bytecode::compile(&tree);
 
-    let tree = parse_file(source_data, &arguments)?;
+            if arguments.is_verbose {
+                println!();
+                println!("[INFO]: Bytecode:");
+                println!("{:#?}", program);
+                println!();
+            }
 
-    execute(&tree)?;
+            bytecode::run(&program, output)?;
+        }
+        Backend::TreeWalker => {
+            execute(&tree, output)?;
+        }
+    }
 
     let end_time = Instant::now();
 
@@ -49,6 +91,16 @@ pub struct Arguments {
     pub source: SourceType,
     pub is_verbose: bool,
     pub ignore_extensions: bool,
+    pub backend: Backend,
+}
+
+// The way the program gets executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    // compile the syntax tree to bytecode and run it on a virtual machine
+    Bytecode,
+    // walk the syntax tree directly, slower but simpler
+    TreeWalker,
 }
 
 pub enum SourceType {
@@ -56,38 +108,59 @@ pub enum SourceType {
     Text(String),
 }
 
-fn get_file_contents(filename: &PathBuf) -> Result<String, String> {
+impl SourceType {
+    // the name of the source used in error messages
+    pub fn name(&self) -> String {
+        match self {
+            SourceType::File(path) => path.display().to_string(),
+            SourceType::Text(_) => String::from("<input>"),
+        }
+    }
+}
+
+fn get_file_contents(filename: &PathBuf) -> Result<String, KodyError> {
     let mut file = File::open(filename).map_err(|_err| {
-        format!(
-            "Unable to read the contents of file {} !",
-            filename.display()
+        KodyError::input(
+            "E0010",
+            format!(
+                "Unable to read the contents of file {} !",
+                filename.display()
+            ),
         )
     })?;
 
     let mut filedata = String::new();
     file.read_to_string(&mut filedata).map_err(|_err| {
-        format!(
-            "The data in file {} was not valid UTF-8 text!",
-            filename.display()
+        KodyError::input(
+            "E0010",
+            format!(
+                "The data in file {} was not valid UTF-8 text!",
+                filename.display()
+            ),
         )
     })?;
 
     Ok(filedata)
 }
 
-fn parse_file(filedata: String, arguments: &Arguments) -> Result<KodySyntaxTree, String> {
+// Tokenizes and parses the source without running it
+pub fn check_syntax(source: &str) -> Result<(), KodyError> {
+    parse_tokens(&tokenize(source)?).map(|_| ())
+}
+
+fn parse_file(filedata: &str, arguments: &Arguments) -> Result<KodySyntaxTree, KodyError> {
     if arguments.is_verbose {
         println!();
         println!("[INFO]: File contents:");
         println!("{}", filedata);
     }
 
-    let tokens = tokenize(&filedata)?;
+    let tokens = tokenize(filedata)?;
 
     if arguments.is_verbose {
         println!();
         println!("[INFO]: Tokens:");
-        println!("{:#?}", tokens);
+        println!("{}", dump_tokens(&tokens));
     }
 
     let tree = parse_tokens(&tokens[..])?;
@@ -95,8 +168,17 @@ fn parse_file(filedata: String, arguments: &Arguments) -> Result<KodySyntaxTree,
     if arguments.is_verbose {
         println!();
         println!("[INFO]: Syntax tree:");
-        println!("{:#?}", tree);
+        println!("{}", dump_tree(&tree));
     }
 
     Ok(tree)
 }
+
+// the dumps shown in verbose mode and by the :tokens and :ast repl commands
+fn dump_tokens(tokens: &[SpannedToken]) -> String {
+    format!("{:#?}", tokens)
+}
+
+fn dump_tree(tree: &KodySyntaxTree) -> String {
+    format!("{:#?}", tree)
+}
@@ -0,0 +1,88 @@
+use std::cmp::Ordering;
+
+use super::Context;
+use crate::runtime::objects::{from_arguments, IntoKody, KodyObject};
+
+pub fn __equal(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (a, b): (KodyObject, KodyObject) = from_arguments(&args, "==")?;
+    Ok(a.value.equals(&b.value).into_kody())
+}
+
+pub fn __not_equal(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (a, b): (KodyObject, KodyObject) = from_arguments(&args, "!=")?;
+    Ok((!a.value.equals(&b.value)).into_kody())
+}
+
+// whether the ordering of two values is one of the accepted ones
+fn compare(
+    args: Vec<KodyObject>,
+    operation_name: &str,
+    accepted: &[Ordering],
+) -> Result<KodyObject, String> {
+    let (a, b): (KodyObject, KodyObject) = from_arguments(&args, operation_name)?;
+    match a.value.compare(&b.value) {
+        Some(ordering) => Ok(accepted.contains(&ordering).into_kody()),
+        None => Err(format!(
+            "Cannot compare {} and {} with {}!",
+            a.value.type_name_with_article(),
+            b.value.type_name_with_article(),
+            operation_name
+        )),
+    }
+}
+
+pub fn __less_than(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    compare(args, "<", &[Ordering::Less])
+}
+
+pub fn __greater_than(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    compare(args, ">", &[Ordering::Greater])
+}
+
+pub fn __less_or_equal(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    compare(args, "<=", &[Ordering::Less, Ordering::Equal])
+}
+
+pub fn __greater_or_equal(
+    _: &mut dyn Context,
+    args: Vec<KodyObject>,
+) -> Result<KodyObject, String> {
+    compare(args, ">=", &[Ordering::Greater, Ordering::Equal])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::libkody::TestContext;
+
+    #[test]
+    fn comparisons() {
+        let call = |function: fn(&mut dyn Context, Vec<KodyObject>) -> _, a, b| {
+            function(&mut TestContext, vec![a, b])
+        };
+        assert_eq!(
+            call(__equal, "a".into_kody(), "a".into_kody()),
+            Ok(true.into_kody())
+        );
+        assert_eq!(
+            call(__not_equal, 1.into_kody(), KodyObject::new()),
+            Ok(true.into_kody())
+        );
+        assert_eq!(
+            call(__less_or_equal, "apple".into_kody(), "apples".into_kody()),
+            Ok(true.into_kody())
+        );
+        assert_eq!(
+            call(__less_than, 1.into_kody(), KodyObject::new()),
+            Err(String::from("Cannot compare a number and an empty with <!"))
+        );
+        assert_eq!(
+            call(__greater_than, true.into_kody(), false.into_kody()),
+            Err(String::from("Cannot compare a bool and a bool with >!"))
+        );
+        assert_eq!(
+            __equal(&mut TestContext, vec![1.into_kody()]),
+            Err(String::from("== takes 2 argument(s) but 1 were given!"))
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::rc::Rc;
+
+use super::Context;
+use crate::runtime::objects::{
+    from_arguments, IntoKody, Iterate, KodyIterator, KodyNumber, KodyObject, KodyValue,
+};
+
+// The iterator a for loop uses to go over a value. Lists give their items,
+// strings their characters and maps their keys. An iterator gives itself.
+pub fn iterate(value: &KodyObject) -> Result<KodyObject, String> {
+    let iterator = match value.value.as_ref() {
+        KodyValue::Iterator(_) => return Ok(value.clone()),
+        KodyValue::List(_) => KodyIterator::new(ListIterator {
+            list: value.clone(),
+            index: 0,
+        }),
+        KodyValue::StringLiteral(val) => KodyIterator::new(Items(
+            val.chars()
+                .map(|c| c.to_string().into_kody())
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )),
+        // the keys are taken when the loop starts,
+        // so changing the map in the loop doesn't change them
+        KodyValue::Map(map) => KodyIterator::new(Items(
+            map.borrow()
+                .iter()
+                .map(|(key, _)| key.to_object())
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )),
+        other => {
+            return Err(format!(
+                "Cannot iterate over {}!",
+                other.type_name_with_article()
+            ))
+        }
+    };
+    Ok(KodyObject::from(KodyValue::Iterator(Rc::new(iterator))))
+}
+
+// Goes over a list by index, so items added to the list
+// during the loop are part of the loop too
+struct ListIterator {
+    list: KodyObject,
+    index: usize,
+}
+
+impl Iterate for ListIterator {
+    fn next_item(&mut self, _: &mut dyn Context) -> Result<Option<KodyObject>, String> {
+        let item = match self.list.value.as_ref() {
+            KodyValue::List(items) => items.borrow().get(self.index).cloned(),
+            _ => None,
+        };
+        self.index += 1;
+        Ok(item)
+    }
+}
+
+struct Items(std::vec::IntoIter<KodyObject>);
+
+impl Iterate for Items {
+    fn next_item(&mut self, _: &mut dyn Context) -> Result<Option<KodyObject>, String> {
+        Ok(self.0.next())
+    }
+}
+
+struct Range {
+    next: KodyNumber,
+    end: KodyNumber,
+    step: KodyNumber,
+}
+
+impl Iterate for Range {
+    fn next_item(&mut self, _: &mut dyn Context) -> Result<Option<KodyObject>, String> {
+        let going_up = self.step > KodyNumber::from_int(0);
+        if (going_up && self.next >= self.end) || (!going_up && self.next <= self.end) {
+            return Ok(None);
+        }
+        let item = self.next.clone();
+        self.next = &self.next + &self.step;
+        Ok(Some(item.into_kody()))
+    }
+}
+
+// range(start, end) counts from start up to but not including end,
+// range(start, end, step) counts in steps that can also be negative
+// or fractions
+pub fn __range(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (start, end, step) = match args.len() {
+        2 => {
+            let (start, end) = from_arguments::<(KodyNumber, KodyNumber)>(&args, "range")?;
+            (start, end, KodyNumber::from_int(1))
+        }
+        3 => from_arguments::<(KodyNumber, KodyNumber, KodyNumber)>(&args, "range")?,
+        count => {
+            return Err(format!(
+                "range takes 2 or 3 argument(s) but {} were given!",
+                count
+            ))
+        }
+    };
+    if step == KodyNumber::from_int(0) {
+        return Err(String::from("The step of a range cannot be zero!"));
+    }
+    Ok(KodyObject::from(KodyValue::Iterator(Rc::new(
+        KodyIterator::new(Range {
+            next: start,
+            end,
+            step,
+        }),
+    ))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::libkody::TestContext;
+
+    fn number(x: i64) -> KodyObject {
+        x.into_kody()
+    }
+
+    // all the items of an iterator as text
+    fn items(value: &KodyObject) -> Vec<String> {
+        let iterator = iterate(value).unwrap();
+        let mut items = vec![];
+        if let KodyValue::Iterator(iterator) = iterator.value.as_ref() {
+            while let Some(item) = iterator.next_item(&mut TestContext).unwrap() {
+                items.push(item.value.to_string());
+            }
+        }
+        items
+    }
+
+    #[test]
+    fn ranges() {
+        let range = |args| __range(&mut TestContext, args).unwrap();
+        assert_eq!(items(&range(vec![number(0), number(3)])), ["0", "1", "2"]);
+        assert_eq!(
+            items(&range(vec![number(5), number(0), number(-2)])),
+            ["5", "3", "1"]
+        );
+        assert_eq!(
+            items(&range(vec![
+                number(0),
+                number(1),
+                KodyNumber::parse("0.25").unwrap().into_kody()
+            ])),
+            ["0", "0.25", "0.5", "0.75"]
+        );
+        assert!(items(&range(vec![number(3), number(0)])).is_empty());
+        assert_eq!(
+            __range(&mut TestContext, vec![number(0), number(1), number(0)]),
+            Err(String::from("The step of a range cannot be zero!"))
+        );
+        assert_eq!(
+            __range(&mut TestContext, vec![number(0)]),
+            Err(String::from(
+                "range takes 2 or 3 argument(s) but 1 were given!"
+            ))
+        );
+
+        // an iterator is used up
+        let range = range(vec![number(0), number(2)]);
+        assert_eq!(items(&range), ["0", "1"]);
+        assert!(items(&range).is_empty());
+    }
+
+    #[test]
+    fn collections() {
+        assert_eq!(items(&"añb".into_kody()), ["a", "ñ", "b"]);
+        assert_eq!(items(&vec![true, false].into_kody()), ["true", "false"]);
+        assert_eq!(
+            iterate(&number(1)),
+            Err(String::from("Cannot iterate over a number!"))
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+use super::{check_argument_count, Context};
+use crate::runtime::objects::{KodyObject, KodyValue};
+
+fn expect_list<'a>(
+    object: &'a KodyObject,
+    function_name: &str,
+) -> Result<&'a RefCell<Vec<KodyObject>>, String> {
+    match object.value.as_ref() {
+        KodyValue::List(items) => Ok(items),
+        _ => Err(format!(
+            "Cannot use {} on an object other than a list!",
+            function_name
+        )),
+    }
+}
+
+// Checks that the index is an integer between 0 and length - 1,
+// or 0 and length when the index can point to the end of the list
+fn expect_index(object: &KodyObject, length: usize, can_be_end: bool) -> Result<usize, String> {
+    let number = match object.value.as_ref() {
+        KodyValue::Number(number) => number,
+        _ => return Err(String::from("List index must be a number!")),
+    };
+    let index = number
+        .to_usize()
+        .ok_or_else(|| format!("List index {} is not a non-negative integer!", number))?;
+
+    if index < length || (can_be_end && index == length) {
+        Ok(index)
+    } else {
+        Err(format!(
+            "List index {} is out of bounds for a list of length {}!",
+            index, length
+        ))
+    }
+}
+
+pub fn from_list(items: Vec<KodyObject>) -> KodyObject {
+    KodyObject::from(KodyValue::List(RefCell::new(items)))
+}
+
+pub fn get_item(items: &[KodyObject], index: &KodyObject) -> Result<KodyObject, String> {
+    let index = expect_index(index, items.len(), false)?;
+    Ok(items[index].clone())
+}
+
+pub fn set_item(
+    items: &mut [KodyObject],
+    index: &KodyObject,
+    value: KodyObject,
+) -> Result<(), String> {
+    let index = expect_index(index, items.len(), false)?;
+    items[index] = value;
+    Ok(())
+}
+
+pub fn remove_item(items: &mut Vec<KodyObject>, index: &KodyObject) -> Result<KodyObject, String> {
+    let index = expect_index(index, items.len(), false)?;
+    Ok(items.remove(index))
+}
+
+pub fn __list(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    Ok(from_list(args))
+}
+
+pub fn __push(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 2, "push")?;
+    expect_list(&args[0], "push")?
+        .borrow_mut()
+        .push(args[1].clone());
+    Ok(KodyObject::new())
+}
+
+pub fn __pop(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 1, "pop")?;
+    expect_list(&args[0], "pop")?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| String::from("Cannot pop from an empty list!"))
+}
+
+pub fn __insert(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 3, "insert")?;
+    let mut items = expect_list(&args[0], "insert")?.borrow_mut();
+    let index = expect_index(&args[1], items.len(), true)?;
+    items.insert(index, args[2].clone());
+    Ok(KodyObject::new())
+}
+
+pub fn __slice(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 3, "slice")?;
+    let items = expect_list(&args[0], "slice")?.borrow();
+    let start = expect_index(&args[1], items.len(), true)?;
+    let end = expect_index(&args[2], items.len(), true)?;
+    if start > end {
+        return Err(format!(
+            "Slice start {} is greater than slice end {}!",
+            start, end
+        ));
+    }
+    Ok(from_list(items[start..end].to_vec()))
+}
+
+pub fn __reverse(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 1, "reverse")?;
+    expect_list(&args[0], "reverse")?.borrow_mut().reverse();
+    Ok(KodyObject::new())
+}
+
+pub fn __sort(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 1, "sort")?;
+    let mut items = expect_list(&args[0], "sort")?.borrow_mut();
+    if !is_sortable(&items) {
+        return Err(String::from(
+            "Cannot sort a list that contains other than only numbers or only strings!",
+        ));
+    }
+    items.sort_by(compare_sortable);
+    Ok(KodyObject::new())
+}
+
+// only lists of numbers or lists of strings can be sorted
+fn is_sortable(items: &[KodyObject]) -> bool {
+    let all_numbers = items
+        .iter()
+        .all(|item| matches!(item.value.as_ref(), KodyValue::Number(_)));
+    let all_strings = items
+        .iter()
+        .all(|item| matches!(item.value.as_ref(), KodyValue::StringLiteral(_)));
+    all_numbers || all_strings
+}
+
+fn compare_sortable(a: &KodyObject, b: &KodyObject) -> Ordering {
+    a.value.compare(&b.value).unwrap_or(Ordering::Equal)
+}
+
+// The functions below call a function for every item. They work on a copy
+// of the items so that the function can change the list while they run.
+
+pub fn __map(context: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 2, "map")?;
+    let items = expect_list(&args[0], "map")?.borrow().clone();
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        results.push(context.call(&args[1], vec![item])?);
+    }
+    Ok(from_list(results))
+}
+
+pub fn __filter(context: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 2, "filter")?;
+    let items = expect_list(&args[0], "filter")?.borrow().clone();
+    let mut kept = vec![];
+    for item in items {
+        match context.call(&args[1], vec![item.clone()])?.value.as_ref() {
+            KodyValue::Bool(true) => kept.push(item),
+            KodyValue::Bool(false) => (),
+            _ => {
+                return Err(String::from(
+                    "The function given to filter must return a bool!",
+                ))
+            }
+        }
+    }
+    Ok(from_list(kept))
+}
+
+// sorts the list in place by the keys the function returns for the items
+pub fn __sort_by(context: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 2, "sort_by")?;
+    let list = expect_list(&args[0], "sort_by")?;
+    let items = list.borrow().clone();
+    let mut keys = Vec::with_capacity(items.len());
+    for item in &items {
+        keys.push(context.call(&args[1], vec![item.clone()])?);
+    }
+    if !is_sortable(&keys) {
+        return Err(String::from(
+            "The function given to sort_by must return only numbers or only strings!",
+        ));
+    }
+
+    let mut keyed_items = keys.into_iter().zip(items).collect::<Vec<_>>();
+    keyed_items.sort_by(|(a, _), (b, _)| compare_sortable(a, b));
+    *list.borrow_mut() = keyed_items.into_iter().map(|(_, item)| item).collect();
+    Ok(KodyObject::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::libkody::{__len, __remove, get_index, set_index, TestContext};
+    use crate::runtime::objects::KodyNumber;
+
+    fn number(x: i64) -> KodyObject {
+        KodyObject::from(KodyValue::Number(KodyNumber::from_int(x)))
+    }
+
+    fn list(items: &[i64]) -> KodyObject {
+        from_list(items.iter().map(|x| number(*x)).collect())
+    }
+
+    #[test]
+    fn shared_changes() {
+        let xs = list(&[3, 1, 2]);
+        __push(&mut TestContext, vec![xs.clone(), number(0)]).unwrap();
+        __sort(&mut TestContext, vec![xs.clone()]).unwrap();
+        assert_eq!(xs, list(&[0, 1, 2, 3]));
+
+        __insert(&mut TestContext, vec![xs.clone(), number(4), number(9)]).unwrap();
+        assert_eq!(
+            __remove(&mut TestContext, vec![xs.clone(), number(0)]),
+            Ok(number(0))
+        );
+        __reverse(&mut TestContext, vec![xs.clone()]).unwrap();
+        assert_eq!(xs, list(&[9, 3, 2, 1]));
+        assert_eq!(__pop(&mut TestContext, vec![xs.clone()]), Ok(number(1)));
+        assert_eq!(__len(&mut TestContext, vec![xs]), Ok(number(3)));
+    }
+
+    #[test]
+    fn indexing() {
+        let xs = list(&[1, 2, 3]);
+        set_index(&xs, &number(1), number(5)).unwrap();
+        assert_eq!(get_index(&xs, &number(1)), Ok(number(5)));
+        assert_eq!(
+            __slice(&mut TestContext, vec![xs.clone(), number(1), number(3)]),
+            Ok(list(&[5, 3]))
+        );
+
+        assert!(get_index(&xs, &number(3)).is_err());
+        assert!(get_index(&xs, &number(-1)).is_err());
+        assert!(get_index(&number(1), &number(0)).is_err());
+        assert!(__slice(&mut TestContext, vec![xs.clone(), number(2), number(1)]).is_err());
+        assert!(__pop(&mut TestContext, vec![list(&[])]).is_err());
+    }
+
+    #[test]
+    fn sort_errors() {
+        let mixed = from_list(vec![
+            number(1),
+            KodyObject::from(KodyValue::StringLiteral(String::from("a"))),
+        ]);
+        assert!(__sort(&mut TestContext, vec![mixed]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::list::from_list;
+use super::{check_argument_count, Context};
+use crate::runtime::objects::{KodyMap, KodyObject, KodyValue, MapKey};
+
+fn expect_map<'a>(
+    object: &'a KodyObject,
+    function_name: &str,
+) -> Result<&'a RefCell<KodyMap>, String> {
+    match object.value.as_ref() {
+        KodyValue::Map(map) => Ok(map),
+        _ => Err(format!(
+            "Cannot use {} on an object other than a map!",
+            function_name
+        )),
+    }
+}
+
+fn expect_key(object: &KodyObject) -> Result<MapKey, String> {
+    // the object isn't shown in the error, it could be the map being changed
+    MapKey::from_object(object)
+        .ok_or_else(|| String::from("Map keys must be strings, numbers or bools!"))
+}
+
+fn missing_key(key: &MapKey) -> String {
+    match key {
+        MapKey::StringLiteral(key) => format!("Key {:?} is not in the map!", key),
+        _ => format!("Key {} is not in the map!", key.to_object().value),
+    }
+}
+
+pub fn get_item(map: &KodyMap, key: &KodyObject) -> Result<KodyObject, String> {
+    let key = expect_key(key)?;
+    map.get(&key).cloned().ok_or_else(|| missing_key(&key))
+}
+
+// setting a key that is not in the map adds it
+pub fn set_item(map: &mut KodyMap, key: &KodyObject, value: KodyObject) -> Result<(), String> {
+    map.insert(expect_key(key)?, value);
+    Ok(())
+}
+
+pub fn remove_item(map: &mut KodyMap, key: &KodyObject) -> Result<KodyObject, String> {
+    let key = expect_key(key)?;
+    map.remove(&key).ok_or_else(|| missing_key(&key))
+}
+
+// the arguments are the keys and values one after another
+pub fn __map(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let mut map = KodyMap::new();
+    for pair in args.chunks(2) {
+        match pair {
+            [key, value] => set_item(&mut map, key, value.clone())?,
+            _ => return Err(String::from("Every map key needs a value!")),
+        }
+    }
+    Ok(KodyObject::from(KodyValue::Map(RefCell::new(map))))
+}
+
+pub fn __keys(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 1, "keys")?;
+    let map = expect_map(&args[0], "keys")?.borrow();
+    Ok(from_list(
+        map.iter().map(|(key, _)| key.to_object()).collect(),
+    ))
+}
+
+pub fn __values(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 1, "values")?;
+    let map = expect_map(&args[0], "values")?.borrow();
+    Ok(from_list(
+        map.iter().map(|(_, value)| value.clone()).collect(),
+    ))
+}
+
+pub fn __has_key(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 2, "has_key")?;
+    let map = expect_map(&args[0], "has_key")?.borrow();
+    let key = expect_key(&args[1])?;
+    Ok(KodyObject {
+        value: Rc::new(KodyValue::Bool(map.get(&key).is_some())),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::libkody::TestContext;
+    use crate::runtime::objects::KodyNumber;
+
+    fn string(val: &str) -> KodyObject {
+        KodyObject::from(KodyValue::StringLiteral(String::from(val)))
+    }
+
+    fn number(x: i64) -> KodyObject {
+        KodyObject::from(KodyValue::Number(KodyNumber::from_int(x)))
+    }
+
+    #[test]
+    fn map_functions() {
+        let map = __map(
+            &mut TestContext,
+            vec![string("b"), number(1), number(2), string("c")],
+        )
+        .unwrap();
+        assert_eq!(
+            __keys(&mut TestContext, vec![map.clone()]),
+            Ok(from_list(vec![string("b"), number(2)]))
+        );
+        assert_eq!(
+            __values(&mut TestContext, vec![map.clone()]),
+            Ok(from_list(vec![number(1), string("c")]))
+        );
+        assert_eq!(
+            __has_key(&mut TestContext, vec![map.clone(), number(2)]),
+            Ok(KodyObject::from(KodyValue::Bool(true)))
+        );
+        assert!(__has_key(&mut TestContext, vec![map.clone(), from_list(vec![])]).is_err());
+        assert!(__keys(&mut TestContext, vec![number(1)]).is_err());
+        assert!(__map(&mut TestContext, vec![string("a")]).is_err());
+    }
+}
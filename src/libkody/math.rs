@@ -1,127 +1,352 @@
-use std::cmp::Ordering;
+use std::f64::consts;
 
-use crate::runtime::objects::{KodyNumber, KodyObject, KodyValue};
+use super::{string, Context};
+use crate::runtime::objects::{
+    from_arguments, from_each_argument, IntoKody, KodyNumber, KodyObject, KodyValue,
+};
 
 fn modify_numbers(
     args: Vec<KodyObject>,
-    operation: fn(&KodyNumber, &KodyNumber) -> KodyNumber,
+    operation: fn(&KodyNumber, &KodyNumber) -> Result<KodyNumber, String>,
     operation_name: &str,
 ) -> Result<KodyObject, String> {
-    if args.len() != 2 {
-        return Err(format!(
-            "Attempting to {} objects other then two objects!",
-            operation_name
-        ));
-    }
-    match (args[0].value.as_ref(), args[1].value.as_ref()) {
-        (KodyValue::Number(val1), KodyValue::Number(val2)) => Ok(KodyObject {
-            value: Box::new(KodyValue::Number(operation(val1, val2))),
-        }),
-        _ => Err(format!(
-            "Cannot {} two objects other than numbers!",
-            operation_name
-        )),
+    let (a, b): (KodyNumber, KodyNumber) = from_arguments(&args, operation_name)?;
+    Ok(operation(&a, &b)?.into_kody())
+}
+
+pub fn __multiply(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    if let [a, b] = args.as_slice() {
+        match (a.value.as_ref(), b.value.as_ref()) {
+            (KodyValue::StringLiteral(text), _) => return string::repeat(text, b),
+            (_, KodyValue::StringLiteral(text)) => return string::repeat(text, a),
+            _ => (),
+        }
     }
+    modify_numbers(args, |a, b| Ok(a * b), "*")
 }
 
-pub fn __multiply(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    modify_numbers(args, |a, b| a * b, "multiply")
+pub fn __divide(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_numbers(
+        args,
+        |a, b| {
+            a.checked_div(b)
+                .ok_or_else(|| String::from("Cannot divide by zero!"))
+        },
+        "/",
+    )
 }
 
-pub fn __divide(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    modify_numbers(args, |a, b| a / b, "divide")
+// numbers are added and strings joined
+pub fn __add(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    if let Some(KodyValue::StringLiteral(_)) = args.first().map(|arg| arg.value.as_ref()) {
+        let (a, b): (String, String) = from_arguments(&args, "+")?;
+        return Ok((a + &b).into_kody());
+    }
+    modify_numbers(args, |a, b| Ok(a + b), "+")
 }
 
-pub fn __add(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    modify_numbers(args, |a, b| a + b, "add")
+pub fn __subtract(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_numbers(args, |a, b| Ok(a - b), "-")
 }
 
-pub fn __subtract(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    modify_numbers(args, |a, b| a - b, "subtract")
+pub fn __negate(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (val,): (KodyNumber,) = from_arguments(&args, "-")?;
+    Ok((-&val).into_kody())
 }
 
-pub fn __negate(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    if args.len() != 1 {
-        return Err(String::from("Cannot negate other than one argument"));
-    }
-    match args[0].value.as_ref() {
-        KodyValue::Number(val) => Ok(KodyObject {
-            value: Box::new(KodyValue::Number(-val)),
-        }),
-        _ => Err(String::from("Cannot negate an object other than a number!")),
+// pi and e can only be approximated
+pub fn constant(name: &str) -> Option<KodyNumber> {
+    match name {
+        "pi" => KodyNumber::approximate(consts::PI),
+        "e" => KodyNumber::approximate(consts::E),
+        _ => None,
     }
 }
 
-fn compare_numbers(args: Vec<KodyObject>) -> Result<Ordering, String> {
-    if args.len() != 2 {
+fn modify_number(
+    args: Vec<KodyObject>,
+    operation: fn(&KodyNumber) -> KodyNumber,
+    operation_name: &str,
+) -> Result<KodyObject, String> {
+    let (x,): (KodyNumber,) = from_arguments(&args, operation_name)?;
+    Ok(operation(&x).into_kody())
+}
+
+pub fn __abs(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_number(args, KodyNumber::abs, "abs")
+}
+
+pub fn __floor(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_number(args, KodyNumber::floor, "floor")
+}
+
+pub fn __ceil(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_number(args, KodyNumber::ceil, "ceil")
+}
+
+pub fn __round(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_number(args, KodyNumber::round, "round")
+}
+
+pub fn __numerator(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_number(args, KodyNumber::numerator, "numerator")
+}
+
+pub fn __denominator(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_number(args, KodyNumber::denominator, "denominator")
+}
+
+// the smallest or largest of one or more numbers
+fn pick_number(
+    args: Vec<KodyObject>,
+    pick: fn(KodyNumber, KodyNumber) -> KodyNumber,
+    operation_name: &str,
+) -> Result<KodyObject, String> {
+    from_each_argument::<KodyNumber>(&args, operation_name)?
+        .into_iter()
+        .reduce(pick)
+        .map(IntoKody::into_kody)
+        .ok_or_else(|| {
+            format!(
+                "{} takes 1 or more argument(s) but 0 were given!",
+                operation_name
+            )
+        })
+}
+
+pub fn __min(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    pick_number(args, std::cmp::min, "min")
+}
+
+pub fn __max(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    pick_number(args, std::cmp::max, "max")
+}
+
+pub fn __gcd(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_numbers(
+        args,
+        |a, b| {
+            a.gcd(b).ok_or_else(|| {
+                format!(
+                    "Cannot find the gcd of {} and {}, they have to be integers!",
+                    a, b
+                )
+            })
+        },
+        "gcd",
+    )
+}
+
+pub fn __lcm(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_numbers(
+        args,
+        |a, b| {
+            a.lcm(b).ok_or_else(|| {
+                format!(
+                    "Cannot find the lcm of {} and {}, they have to be integers!",
+                    a, b
+                )
+            })
+        },
+        "lcm",
+    )
+}
+
+// only integer exponents keep the result exact
+pub fn __pow(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    modify_numbers(
+        args,
+        |base, exponent| {
+            base.checked_pow(exponent).ok_or_else(|| {
+                if !exponent.is_integer() {
+                    format!(
+                        "The exponent of pow has to be an integer but got {}!",
+                        exponent
+                    )
+                } else if base == &KodyNumber::from(0) && exponent < &KodyNumber::from(0) {
+                    String::from("Cannot raise 0 to a negative power!")
+                } else {
+                    String::from("The result of pow would be too large!")
+                }
+            })
+        },
+        "pow",
+    )
+}
+
+// Results that can't be exact are rounded to the closest float
+// and marked as approximate
+fn approximate(
+    args: Vec<KodyObject>,
+    operation: fn(f64) -> f64,
+    operation_name: &str,
+) -> Result<KodyObject, String> {
+    let (x,): (f64,) = from_arguments(&args, operation_name)?;
+    to_approximation(operation(x), operation_name)
+}
+
+fn to_approximation(result: f64, operation_name: &str) -> Result<KodyObject, String> {
+    KodyNumber::approximate(result)
+        .map(IntoKody::into_kody)
+        .ok_or_else(|| {
+            format!(
+                "The result of {} is too large to approximate!",
+                operation_name
+            )
+        })
+}
+
+pub fn __sqrt(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (x,): (KodyNumber,) = from_arguments(&args, "sqrt")?;
+    if x < KodyNumber::from(0) {
         return Err(String::from(
-            "Attempting to compare objects other then two objects!",
+            "Cannot take the square root of a negative number!",
         ));
     }
-    match (args[0].value.as_ref(), args[1].value.as_ref()) {
-        (KodyValue::Number(val1), KodyValue::Number(val2)) => Ok(val1.cmp(&val2)),
-        _ => Err(String::from(
-            "Cannot compare two objects other than numbers!",
-        )),
+    to_approximation(x.to_f64().sqrt(), "sqrt")
+}
+
+pub fn __exp(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    approximate(args, f64::exp, "exp")
+}
+
+pub fn __ln(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (x,): (KodyNumber,) = from_arguments(&args, "ln")?;
+    if x <= KodyNumber::from(0) {
+        return Err(String::from(
+            "The logarithm is only defined for positive numbers!",
+        ));
     }
+    to_approximation(x.to_f64().ln(), "ln")
+}
+
+pub fn __sin(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    approximate(args, f64::sin, "sin")
 }
 
-pub fn __less_than(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    Ok(KodyObject {
-        value: Box::new(KodyValue::Bool(match compare_numbers(args)? {
-            Ordering::Equal => false,
-            Ordering::Less => true,
-            Ordering::Greater => false,
-        })),
-    })
-}
-
-pub fn __greater_than(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    Ok(KodyObject {
-        value: Box::new(KodyValue::Bool(match compare_numbers(args)? {
-            Ordering::Equal => false,
-            Ordering::Less => false,
-            Ordering::Greater => true,
-        })),
-    })
-}
-
-pub fn __less_or_equal(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    Ok(KodyObject {
-        value: Box::new(KodyValue::Bool(match compare_numbers(args)? {
-            Ordering::Equal => true,
-            Ordering::Less => true,
-            Ordering::Greater => false,
-        })),
-    })
-}
-
-pub fn __greater_or_equal(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    Ok(KodyObject {
-        value: Box::new(KodyValue::Bool(match compare_numbers(args)? {
-            Ordering::Equal => true,
-            Ordering::Less => false,
-            Ordering::Greater => true,
-        })),
-    })
-}
-
-pub fn __equal(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    Ok(KodyObject {
-        value: Box::new(KodyValue::Bool(match compare_numbers(args)? {
-            Ordering::Equal => true,
-            Ordering::Less => false,
-            Ordering::Greater => false,
-        })),
-    })
-}
-
-pub fn __not_equal(args: Vec<KodyObject>) -> Result<KodyObject, String> {
-    Ok(KodyObject {
-        value: Box::new(KodyValue::Bool(match compare_numbers(args)? {
-            Ordering::Equal => false,
-            Ordering::Less => true,
-            Ordering::Greater => true,
-        })),
-    })
+pub fn __cos(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    approximate(args, f64::cos, "cos")
+}
+
+// the angle of the point (x, y), atan2(y, x) like in other languages
+pub fn __atan2(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (y, x): (f64, f64) = from_arguments(&args, "atan2")?;
+    to_approximation(y.atan2(x), "atan2")
+}
+
+// opts into float arithmetic for a number, everything computed
+// from it is approximate too
+pub fn __approximate(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (x,): (KodyNumber,) = from_arguments(&args, "approximate")?;
+    Ok(x.to_approximate().into_kody())
+}
+
+pub fn __is_exact(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (x,): (KodyNumber,) = from_arguments(&args, "is_exact")?;
+    Ok((!x.is_approximate()).into_kody())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::libkody::TestContext;
+    use crate::runtime::objects::BuiltinFunction;
+
+    fn number(x: i64) -> KodyObject {
+        KodyObject::from(KodyValue::Number(KodyNumber::from_int(x)))
+    }
+
+    #[test]
+    fn divide() {
+        assert_eq!(
+            __divide(&mut TestContext, vec![number(6), number(3)]),
+            Ok(number(2))
+        );
+        assert_eq!(
+            __divide(&mut TestContext, vec![number(1), number(0)]),
+            Err(String::from("Cannot divide by zero!"))
+        );
+    }
+
+    #[test]
+    fn exact_operations() {
+        let call = |function: BuiltinFunction, args| function(&mut TestContext, args);
+        assert_eq!(
+            call(__min, vec![number(3), number(-7), number(5)]),
+            Ok(number(-7))
+        );
+        assert_eq!(
+            call(__max, vec![]),
+            Err(String::from(
+                "max takes 1 or more argument(s) but 0 were given!"
+            ))
+        );
+        assert_eq!(
+            call(__max, vec![number(1), "2".into_kody()]),
+            Err(String::from(
+                "Argument 2 of max: expected a number but got a string!"
+            ))
+        );
+        assert_eq!(
+            call(__pow, vec![number(0), number(-2)]),
+            Err(String::from("Cannot raise 0 to a negative power!"))
+        );
+        assert_eq!(
+            call(__pow, vec![number(10), number(10_000_000)]),
+            Err(String::from("The result of pow would be too large!"))
+        );
+        let half = KodyNumber::parse("0.5").unwrap().into_kody();
+        assert_eq!(
+            call(__gcd, vec![number(1), half]),
+            Err(String::from(
+                "Cannot find the gcd of 1 and 0.5, they have to be integers!"
+            ))
+        );
+    }
+
+    #[test]
+    fn approximate_operations() {
+        let call = |function: BuiltinFunction, args| function(&mut TestContext, args);
+        let is_exact = |result: Result<KodyObject, String>| match result.unwrap().value.as_ref() {
+            KodyValue::Number(x) => !x.is_approximate(),
+            _ => panic!("expected a number"),
+        };
+        assert!(!is_exact(call(__sqrt, vec![number(4)])));
+        assert_eq!(call(__sqrt, vec![number(4)]), Ok(number(2)));
+        assert_eq!(
+            call(__sqrt, vec![number(-1)]),
+            Err(String::from(
+                "Cannot take the square root of a negative number!"
+            ))
+        );
+        assert_eq!(
+            call(__ln, vec![number(0)]),
+            Err(String::from(
+                "The logarithm is only defined for positive numbers!"
+            ))
+        );
+        assert_eq!(
+            call(__exp, vec![number(1000)]),
+            Err(String::from(
+                "The result of exp is too large to approximate!"
+            ))
+        );
+        assert_eq!(call(__atan2, vec![number(0), number(1)]), Ok(number(0)));
+        assert!(constant("pi").unwrap().is_approximate());
+        assert_eq!(constant("tau"), None);
+    }
+
+    #[test]
+    fn argument_errors() {
+        assert_eq!(
+            __add(&mut TestContext, vec![number(1)]),
+            Err(String::from("+ takes 2 argument(s) but 1 were given!"))
+        );
+        assert_eq!(
+            __subtract(&mut TestContext, vec![number(1), KodyObject::new()]),
+            Err(String::from(
+                "Argument 2 of -: expected a number but got an empty!"
+            ))
+        );
+    }
 }
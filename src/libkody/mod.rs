@@ -1,49 +1,239 @@
+mod compare;
+mod iterator;
+mod list;
 mod logic;
+mod map;
 mod math;
+mod string;
+mod types;
 
 use std::collections::HashMap;
+use std::rc::Rc;
 
-use crate::runtime::objects::{KodyObject, KodyValue};
+pub use iterator::iterate;
+
+use crate::runtime::objects::{
+    check_argument_count, BuiltinFunction, KodyNumber, KodyObject, KodyValue, MapKey,
+    NativeFunction,
+};
 
 // GLOBALS contains all globally available functions
 lazy_static! {
-    pub static ref GLOBALS: HashMap<&'static str, KodyObject> = {
+    pub static ref GLOBALS: HashMap<&'static str, BuiltinFunction> = {
         [
-            // the as fn(..) -> Result<..> is there to stop an error
-            (
-                "print",
-                __print as fn(Vec<KodyObject>) -> Result<KodyObject, String>,
-            ),
-            ("__equal", math::__equal),
-            ("__not_equal", math::__not_equal),
-            ("__less_than", math::__less_than),
-            ("__less_than_or_equal", math::__less_or_equal),
-            ("__greater_than", math::__greater_than),
-            ("__greater_than_or_equal", math::__greater_or_equal),
+            // the as BuiltinFunction is there to stop an error
+            ("print", __print as BuiltinFunction),
+            ("__equal", compare::__equal),
+            ("__not_equal", compare::__not_equal),
+            ("__less_than", compare::__less_than),
+            ("__less_than_or_equal", compare::__less_or_equal),
+            ("__greater_than", compare::__greater_than),
+            ("__greater_than_or_equal", compare::__greater_or_equal),
             ("__add", math::__add),
             ("__subtract", math::__subtract),
             ("__multiply", math::__multiply),
             ("__divide", math::__divide),
             ("__negate", math::__negate),
+            ("abs", math::__abs),
+            ("floor", math::__floor),
+            ("ceil", math::__ceil),
+            ("round", math::__round),
+            ("min", math::__min),
+            ("max", math::__max),
+            ("gcd", math::__gcd),
+            ("lcm", math::__lcm),
+            ("numerator", math::__numerator),
+            ("denominator", math::__denominator),
+            ("pow", math::__pow),
+            ("sqrt", math::__sqrt),
+            ("exp", math::__exp),
+            ("ln", math::__ln),
+            ("sin", math::__sin),
+            ("cos", math::__cos),
+            ("atan2", math::__atan2),
+            ("approximate", math::__approximate),
+            ("is_exact", math::__is_exact),
             ("__not", logic::__not),
             ("__and", logic::__and),
             ("__or", logic::__or),
+            ("__list", list::__list),
+            ("len", __len),
+            ("push", list::__push),
+            ("pop", list::__pop),
+            ("insert", list::__insert),
+            ("remove", __remove),
+            ("slice", list::__slice),
+            ("reverse", list::__reverse),
+            ("sort", list::__sort),
+            ("map", list::__map),
+            ("filter", list::__filter),
+            ("sort_by", list::__sort_by),
+            ("__map", map::__map),
+            ("keys", map::__keys),
+            ("values", map::__values),
+            ("has_key", map::__has_key),
+            ("range", iterator::__range),
+            ("__format", string::__format),
+            ("substring", string::__substring),
+            ("split", string::__split),
+            ("join", string::__join),
+            ("trim", string::__trim),
+            ("upper", string::__upper),
+            ("lower", string::__lower),
+            ("replace", string::__replace),
+            ("find", string::__find),
+            ("starts_with", string::__starts_with),
+            ("ends_with", string::__ends_with),
+            ("type_of", types::__type_of),
+            ("str", types::__str),
+            ("num", types::__num),
+            ("bool", types::__bool),
         ]
         .iter()
-        .map(|(name, func)| (*name, KodyObject::from(KodyValue::NativeFunction(*func))))
+        .copied()
         .collect()
     };
 }
 
-fn __print(args: Vec<KodyObject>) -> Result<KodyObject, String> {
+// The value of a name that isn't a variable of the program,
+// a constant like pi or a native function
+pub fn get_global(name: &str) -> Option<KodyObject> {
+    if let Some(number) = math::constant(name) {
+        return Some(KodyObject::from(KodyValue::Number(number)));
+    }
+    GLOBALS.get(name).map(|function| {
+        KodyObject::from(KodyValue::NativeFunction(NativeFunction::Builtin(
+            *function,
+        )))
+    })
+}
+
+// The backend that runs a native function,
+// it lets the native call functions of the program
+pub trait Context {
+    // the error message of a failing call is only for the native to pass on,
+    // the backend reports the original error
+    fn call(
+        &mut self,
+        function: &KodyObject,
+        arguments: Vec<KodyObject>,
+    ) -> Result<KodyObject, String>;
+
+    // everything a program prints goes through here
+    fn write_output(&mut self, text: &str) -> Result<(), String>;
+}
+
+// for testing natives that don't call functions
+#[cfg(test)]
+pub struct TestContext;
+
+#[cfg(test)]
+impl Context for TestContext {
+    fn call(&mut self, _: &KodyObject, _: Vec<KodyObject>) -> Result<KodyObject, String> {
+        Err(String::from("Cannot call functions in tests!"))
+    }
+
+    fn write_output(&mut self, _: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn __print(context: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let mut line = String::new();
     for arg in args {
-        match *arg.value {
-            KodyValue::StringLiteral(val) => print!("{}", val),
-            KodyValue::Number(val) => print!("{}", val),
-            KodyValue::Bool(val) => print!("{}", val),
-            _ => print!("{:?}", arg.value),
-        }
+        line += &arg.value.to_string();
     }
-    println!();
+    line.push('\n');
+    context.write_output(&line)?;
     Ok(KodyObject::new())
 }
+
+// xs[i] and m[key]
+pub fn get_index(base: &KodyObject, index: &KodyObject) -> Result<KodyObject, String> {
+    match base.value.as_ref() {
+        KodyValue::List(items) => list::get_item(&items.borrow(), index),
+        KodyValue::Map(map) => map::get_item(&map.borrow(), index),
+        _ => Err(String::from(
+            "Cannot index an object other than a list or a map!",
+        )),
+    }
+}
+
+// xs[i] = value and m[key] = value
+pub fn set_index(base: &KodyObject, index: &KodyObject, value: KodyObject) -> Result<(), String> {
+    match base.value.as_ref() {
+        KodyValue::List(items) => list::set_item(&mut items.borrow_mut(), index, value),
+        KodyValue::Map(map) => map::set_item(&mut map.borrow_mut(), index, value),
+        _ => Err(String::from(
+            "Cannot index an object other than a list or a map!",
+        )),
+    }
+}
+
+// obj.member reads a string key of a map, anything else is looked up from
+// the native functions so that xs.push(1) does the same as push(xs, 1)
+pub fn get_member(base: &KodyObject, member: &str) -> Result<KodyObject, String> {
+    if let KodyValue::Map(map) = base.value.as_ref() {
+        if let Some(value) = map.borrow().get(&MapKey::StringLiteral(member.to_string())) {
+            return Ok(value.clone());
+        }
+    }
+
+    match GLOBALS.get(member) {
+        // operators aren't methods
+        Some(function) if !member.starts_with("__") => Ok(KodyObject::from(KodyValue::Method {
+            receiver: base.clone(),
+            function: NativeFunction::Builtin(*function),
+        })),
+        _ => Err(format!(
+            "A {} has no member named {}!",
+            base.value.type_name(),
+            member
+        )),
+    }
+}
+
+// obj.member = value sets a string key of a map
+pub fn set_member(base: &KodyObject, member: &str, value: KodyObject) -> Result<(), String> {
+    match base.value.as_ref() {
+        KodyValue::Map(map) => {
+            map.borrow_mut()
+                .insert(MapKey::StringLiteral(member.to_string()), value);
+            Ok(())
+        }
+        other => Err(format!(
+            "Cannot set member {} of a {}!",
+            member,
+            other.type_name()
+        )),
+    }
+}
+
+fn __len(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 1, "len")?;
+    let length = match args[0].value.as_ref() {
+        KodyValue::List(items) => items.borrow().len(),
+        KodyValue::Map(map) => map.borrow().len(),
+        KodyValue::StringLiteral(val) => val.chars().count(),
+        _ => {
+            return Err(String::from(
+                "Cannot use len on an object other than a list, a map or a string!",
+            ))
+        }
+    };
+    Ok(KodyObject {
+        value: Rc::new(KodyValue::Number(KodyNumber::from_int(length as i64))),
+    })
+}
+
+// removes an item from a list by its index or from a map by its key
+fn __remove(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    check_argument_count(&args, 2, "remove")?;
+    match args[0].value.as_ref() {
+        KodyValue::List(items) => list::remove_item(&mut items.borrow_mut(), &args[1]),
+        KodyValue::Map(map) => map::remove_item(&mut map.borrow_mut(), &args[1]),
+        _ => Err(String::from(
+            "Cannot use remove on an object other than a list or a map!",
+        )),
+    }
+}
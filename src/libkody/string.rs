@@ -0,0 +1,258 @@
+// Strings are indexed by their characters, the Unicode scalar values
+// that \U+xxxx writes, so "ä" has a length of 1 like "a" does.
+
+use super::Context;
+use crate::runtime::objects::{from_arguments, IntoKody, KodyNumber, KodyObject, KodyValue};
+
+// how many bytes a repeated string can have
+const MAX_REPEAT_BYTES: usize = 1 << 26;
+
+// Checks that the index is an integer between 0 and the length of the string
+fn expect_index(number: &KodyNumber, length: usize) -> Result<usize, String> {
+    let index = number
+        .to_usize()
+        .ok_or_else(|| format!("String index {} is not a non-negative integer!", number))?;
+    if index <= length {
+        Ok(index)
+    } else {
+        Err(format!(
+            "String index {} is out of bounds for a string of length {}!",
+            index, length
+        ))
+    }
+}
+
+// the character index of a byte offset in text
+fn char_index(text: &str, byte_offset: usize) -> usize {
+    text[..byte_offset].chars().count()
+}
+
+// f"..." strings are joined from their parts,
+// values other than strings are written the way print writes them
+pub fn __format(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    Ok(args
+        .iter()
+        .map(|arg| arg.value.to_string())
+        .collect::<String>()
+        .into_kody())
+}
+
+// "ab" * 3 and 3 * "ab" repeat the string
+pub fn repeat(text: &str, count: &KodyObject) -> Result<KodyObject, String> {
+    let count = match count.value.as_ref() {
+        KodyValue::Number(number) => number
+            .to_usize()
+            .ok_or_else(|| format!("Cannot repeat a string {} times!", number))?,
+        other => {
+            return Err(format!(
+                "Cannot multiply a string by {}!",
+                other.type_name_with_article()
+            ))
+        }
+    };
+    match text.len().checked_mul(count) {
+        Some(length) if length <= MAX_REPEAT_BYTES => (),
+        _ => {
+            return Err(format!(
+                "The repeated string would be longer than {} bytes!",
+                MAX_REPEAT_BYTES
+            ))
+        }
+    }
+    Ok(text.repeat(count).into_kody())
+}
+
+// substring(text, start) or substring(text, start, end),
+// the end is not part of the substring
+pub fn __substring(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text, start, end) = match args.len() {
+        2 => {
+            let (text, start) = from_arguments::<(String, KodyNumber)>(&args, "substring")?;
+            (text, start, None)
+        }
+        3 => {
+            let (text, start, end) =
+                from_arguments::<(String, KodyNumber, KodyNumber)>(&args, "substring")?;
+            (text, start, Some(end))
+        }
+        count => {
+            return Err(format!(
+                "substring takes 2 or 3 argument(s) but {} were given!",
+                count
+            ))
+        }
+    };
+    let length = text.chars().count();
+    let start = expect_index(&start, length)?;
+    let end = match end {
+        Some(end) => expect_index(&end, length)?,
+        None => length,
+    };
+    if start > end {
+        return Err(format!(
+            "Substring start {} is greater than substring end {}!",
+            start, end
+        ));
+    }
+    Ok(text
+        .chars()
+        .skip(start)
+        .take(end - start)
+        .collect::<String>()
+        .into_kody())
+}
+
+// an empty separator splits the string into its characters
+pub fn __split(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text, separator): (String, String) = from_arguments(&args, "split")?;
+    let parts: Vec<String> = if separator.is_empty() {
+        text.chars().map(String::from).collect()
+    } else {
+        text.split(&separator).map(String::from).collect()
+    };
+    Ok(parts.into_kody())
+}
+
+pub fn __join(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (parts, separator): (Vec<String>, String) = from_arguments(&args, "join")?;
+    Ok(parts.join(&separator).into_kody())
+}
+
+pub fn __trim(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text,): (String,) = from_arguments(&args, "trim")?;
+    Ok(text.trim().into_kody())
+}
+
+pub fn __upper(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text,): (String,) = from_arguments(&args, "upper")?;
+    Ok(text.to_uppercase().into_kody())
+}
+
+pub fn __lower(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text,): (String,) = from_arguments(&args, "lower")?;
+    Ok(text.to_lowercase().into_kody())
+}
+
+// replaces every occurrence
+pub fn __replace(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text, from, to): (String, String, String) = from_arguments(&args, "replace")?;
+    Ok(text.replace(&from, &to).into_kody())
+}
+
+// the index of the first occurrence or empty if there is none
+pub fn __find(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text, part): (String, String) = from_arguments(&args, "find")?;
+    Ok(match text.find(&part) {
+        Some(byte_offset) => char_index(&text, byte_offset).into_kody(),
+        None => KodyObject::new(),
+    })
+}
+
+pub fn __starts_with(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text, part): (String, String) = from_arguments(&args, "starts_with")?;
+    Ok(text.starts_with(&part).into_kody())
+}
+
+pub fn __ends_with(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let (text, part): (String, String) = from_arguments(&args, "ends_with")?;
+    Ok(text.ends_with(&part).into_kody())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::libkody::TestContext;
+
+    fn call(
+        function: fn(&mut dyn Context, Vec<KodyObject>) -> Result<KodyObject, String>,
+        args: Vec<KodyObject>,
+    ) -> Result<String, String> {
+        function(&mut TestContext, args).map(|result| result.value.to_string())
+    }
+
+    fn text(value: &str) -> KodyObject {
+        value.into_kody()
+    }
+
+    #[test]
+    fn substrings() {
+        let greeting = text("hyvää päivää");
+        assert_eq!(
+            call(
+                __substring,
+                vec![greeting.clone(), 2.into_kody(), 5.into_kody()]
+            ),
+            Ok(String::from("vää"))
+        );
+        assert_eq!(
+            call(__substring, vec![greeting.clone(), 6.into_kody()]),
+            Ok(String::from("päivää"))
+        );
+        assert_eq!(
+            call(__substring, vec![greeting.clone(), 13.into_kody()]),
+            Err(String::from(
+                "String index 13 is out of bounds for a string of length 12!"
+            ))
+        );
+        assert_eq!(
+            call(__substring, vec![greeting, 3.into_kody(), 2.into_kody()]),
+            Err(String::from(
+                "Substring start 3 is greater than substring end 2!"
+            ))
+        );
+        assert_eq!(
+            call(__find, vec![text("päivää"), text("vää")]),
+            Ok(String::from("3"))
+        );
+        assert_eq!(
+            call(__find, vec![text("abc"), text("d")]),
+            Ok(String::from("Empty"))
+        );
+    }
+
+    #[test]
+    fn splitting() {
+        assert_eq!(
+            call(__split, vec![text("a, b, c"), text(", ")]),
+            Ok(String::from("[\"a\", \"b\", \"c\"]"))
+        );
+        assert_eq!(
+            call(__split, vec![text("añ"), text("")]),
+            Ok(String::from("[\"a\", \"ñ\"]"))
+        );
+        assert_eq!(
+            call(__join, vec![vec!["a", "b"].into_kody(), text("-")]),
+            Ok(String::from("a-b"))
+        );
+        assert_eq!(
+            call(__join, vec![vec![1, 2].into_kody(), text("-")]),
+            Err(String::from(
+                "Argument 1 of join: expected a string but got a number!"
+            ))
+        );
+    }
+
+    #[test]
+    fn repetition() {
+        assert_eq!(
+            repeat("ab", &3.into_kody()).map(|x| x.value.to_string()),
+            Ok(String::from("ababab"))
+        );
+        assert_eq!(
+            repeat("ab", &(-1).into_kody()),
+            Err(String::from("Cannot repeat a string -1 times!"))
+        );
+        assert_eq!(
+            repeat("ab", &text("c")),
+            Err(String::from("Cannot multiply a string by a string!"))
+        );
+        assert!(repeat("ab", &usize::MAX.into_kody()).is_err());
+        assert_eq!(
+            repeat("ab", &1_000_000_000_000_000_000u64.into_kody()),
+            Err(String::from(
+                "The repeated string would be longer than 67108864 bytes!"
+            ))
+        );
+        assert!(repeat("", &1_000_000_000_000_000_000u64.into_kody()).is_ok());
+    }
+}
@@ -0,0 +1,135 @@
+// Asking what type a value is and converting values between types
+
+use super::Context;
+use crate::runtime::objects::{check_argument_count, IntoKody, KodyNumber, KodyObject, KodyValue};
+use crate::tokenizer::tokenize_number_literal;
+
+// the one argument of a conversion
+fn single_argument(args: Vec<KodyObject>, name: &str) -> Result<KodyObject, String> {
+    check_argument_count(&args, 1, name)?;
+    Ok(args.into_iter().next().unwrap())
+}
+
+pub fn __type_of(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let value = single_argument(args, "type_of")?;
+    Ok(value.value.type_name().into_kody())
+}
+
+// the text print would write for the value
+pub fn __str(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let value = single_argument(args, "str")?;
+    Ok(value.value.to_string().into_kody())
+}
+
+// Strings are read like number literals in the source, with an optional
+// minus sign in front and whitespace around them
+pub fn __num(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let value = single_argument(args, "num")?;
+    let text = match value.value.as_ref() {
+        KodyValue::Number(_) => return Ok(value),
+        KodyValue::StringLiteral(text) => text,
+        other => {
+            return Err(format!(
+                "Cannot convert {} to a number!",
+                other.type_name_with_article()
+            ))
+        }
+    };
+    let trimmed = text.trim();
+    let (is_negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed),
+    };
+    let number = tokenize_number_literal(digits)
+        .map_err(|error| format!("Cannot convert {:?} to a number! {}", text, error.message()))
+        .and_then(|literal| {
+            KodyNumber::parse(&literal)
+                .ok_or_else(|| format!("Cannot convert {:?} to a number!", text))
+        })?;
+    Ok(if is_negative { -&number } else { number }.into_kody())
+}
+
+// only bools and the strings "true" and "false" are converted,
+// other values have no obvious truth value
+pub fn __bool(_: &mut dyn Context, args: Vec<KodyObject>) -> Result<KodyObject, String> {
+    let value = single_argument(args, "bool")?;
+    match value.value.as_ref() {
+        KodyValue::Bool(_) => Ok(value),
+        KodyValue::StringLiteral(text) => match text.trim() {
+            "true" => Ok(true.into_kody()),
+            "false" => Ok(false.into_kody()),
+            _ => Err(format!("Cannot convert {:?} to a bool!", text)),
+        },
+        other => Err(format!(
+            "Cannot convert {} to a bool!",
+            other.type_name_with_article()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::libkody::TestContext;
+
+    fn call(
+        function: fn(&mut dyn Context, Vec<KodyObject>) -> Result<KodyObject, String>,
+        arg: KodyObject,
+    ) -> Result<String, String> {
+        function(&mut TestContext, vec![arg]).map(|result| result.value.to_string())
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(call(__type_of, 1.into_kody()), Ok(String::from("number")));
+        assert_eq!(
+            call(__type_of, KodyObject::new()),
+            Ok(String::from("empty"))
+        );
+        assert_eq!(
+            call(__type_of, vec!["a"].into_kody()),
+            Ok(String::from("list"))
+        );
+        assert_eq!(
+            call(__str, vec!["a"].into_kody()),
+            Ok(String::from("[\"a\"]"))
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(call(__num, "3.25".into_kody()), Ok(String::from("3.25")));
+        assert_eq!(
+            call(__num, " -1_000 ".into_kody()),
+            Ok(String::from("-1000"))
+        );
+        assert_eq!(call(__num, 7.into_kody()), Ok(String::from("7")));
+        assert_eq!(
+            call(__num, "1.2.3".into_kody()),
+            Err(String::from(
+                "Cannot convert \"1.2.3\" to a number! Multiple decimal separators in one number!"
+            ))
+        );
+        assert!(call(__num, "".into_kody()).is_err());
+        assert!(call(__num, "--1".into_kody()).is_err());
+        assert!(call(__num, "1e5".into_kody()).is_err());
+        assert_eq!(
+            call(__num, true.into_kody()),
+            Err(String::from("Cannot convert a bool to a number!"))
+        );
+    }
+
+    #[test]
+    fn bools() {
+        assert_eq!(call(__bool, "true".into_kody()), Ok(String::from("true")));
+        assert_eq!(call(__bool, false.into_kody()), Ok(String::from("false")));
+        assert_eq!(
+            call(__bool, "yes".into_kody()),
+            Err(String::from("Cannot convert \"yes\" to a bool!"))
+        );
+        assert_eq!(
+            call(__bool, 1.into_kody()),
+            Err(String::from("Cannot convert a number to a bool!"))
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// An interactive read-eval-print loop.
+//
+// Every input runs in the same session, so variables and functions stay
+// defined between inputs. An input continues on the next line while it has
+// unclosed brackets or strings, an empty line ends it anyway.
+
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::rc::Rc;
+
+use crate::error::KodyError;
+use crate::runtime::objects::KodyValue;
+use crate::runtime::Session;
+use crate::syntax_tree::{parse_input, KodyNode};
+use crate::tokenizer::{tokenize, Token};
+use crate::{dump_tokens, dump_tree};
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const SOURCE_NAME: &str = "<repl>";
+
+const HELP: &str = "\
+Enter code to run it, the value of every expression is printed.
+Commands:
+  :tokens <code>  show the tokens of the code
+  :ast <code>     show the syntax tree of the code
+  :help           show this message
+  :quit           exit the repl (or press Ctrl-D)
+";
+
+pub fn run_repl<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut session = Session::new();
+    // what the code prints is collected and written out after
+    // each statement, before the value of the statement
+    let printed = Rc::new(RefCell::new(vec![]));
+    session.set_output(printed.clone());
+
+    loop {
+        let source = match read_input(&mut input, &mut output)? {
+            Some(source) => source,
+            // end of the input, finish the line of the prompt
+            None => return writeln!(output),
+        };
+        let trimmed = source.trim();
+
+        if trimmed.is_empty() {
+            continue;
+        } else if trimmed == ":quit" {
+            return Ok(());
+        } else if trimmed == ":help" {
+            write!(output, "{}", HELP)?;
+        } else if let Some(code) = trimmed.strip_prefix(":tokens") {
+            match tokenize(code) {
+                Ok(tokens) => writeln!(output, "{}", dump_tokens(&tokens))?,
+                Err(error) => write!(output, "{}", error.render(code, SOURCE_NAME))?,
+            }
+        } else if let Some(code) = trimmed.strip_prefix(":ast") {
+            match tokenize(code).and_then(|tokens| parse_input(&tokens)) {
+                Ok(tree) => writeln!(output, "{}", dump_tree(&tree))?,
+                Err(error) => write!(output, "{}", error.render(code, SOURCE_NAME))?,
+            }
+        } else if trimmed.starts_with(':') {
+            writeln!(output, "Unknown command {}, try :help", trimmed)?;
+        } else if let Err(error) = evaluate(&mut session, &source, &printed, &mut output)? {
+            write!(output, "{}", error.render(&source, SOURCE_NAME))?;
+        }
+    }
+}
+
+// Reads lines until the input is complete. Returns None when there is
+// nothing left to read.
+fn read_input<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> io::Result<Option<String>> {
+    let mut source = String::new();
+    loop {
+        write!(
+            output,
+            "{}",
+            if source.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            }
+        )?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(if source.is_empty() {
+                None
+            } else {
+                Some(source)
+            });
+        }
+        if !source.is_empty() && line.trim().is_empty() {
+            return Ok(Some(source));
+        }
+        source.push_str(&line);
+
+        if !is_incomplete(&source) {
+            return Ok(Some(source));
+        }
+    }
+}
+
+// true if the source has unclosed brackets or strings
+fn is_incomplete(source: &str) -> bool {
+    match tokenize(source) {
+        Ok(tokens) => {
+            let mut depth = 0;
+            for token in tokens {
+                match token.token {
+                    Token::OpenParentheses
+                    | Token::OpenCurlyBrackets
+                    | Token::OpenSquareBrackets => depth += 1,
+                    Token::CloseParentheses
+                    | Token::CloseCurlyBrackets
+                    | Token::CloseSquareBrackets => depth -= 1,
+                    _ => (),
+                }
+            }
+            depth > 0
+        }
+        Err(error) => error.code() == "E0004",
+    }
+}
+
+// Runs the source and prints the value of every statement that has one.
+// The outer result is for errors writing the output.
+fn evaluate<W: Write>(
+    session: &mut Session,
+    source: &str,
+    printed: &Rc<RefCell<Vec<u8>>>,
+    output: &mut W,
+) -> io::Result<Result<(), KodyError>> {
+    let tree = match tokenize(source).and_then(|tokens| parse_input(&tokens)) {
+        Ok(tree) => tree,
+        Err(error) => return Ok(Err(error)),
+    };
+
+    let statements = match &tree.main {
+        KodyNode::CodeBlock { statements, .. } => statements.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    session.declare_functions(statements);
+    for statement in statements {
+        let result = session.execute_statement(statement);
+        output.write_all(&mem::take(&mut *printed.borrow_mut()))?;
+        let value = match result {
+            Ok(value) => value,
+            Err(error) => return Ok(Err(error)),
+        };
+        match value.value.as_ref() {
+            KodyValue::Empty => (),
+            // quoted so that strings can be told apart from other values
+            KodyValue::StringLiteral(val) => writeln!(output, "{:?}", val)?,
+            other => writeln!(output, "{}", other)?,
+        }
+    }
+
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_repl;
+
+    fn repl(input: &str) -> String {
+        let mut output = vec![];
+        run_repl(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .replace(super::PROMPT, "")
+            .replace(super::CONTINUATION_PROMPT, "")
+    }
+
+    #[test]
+    fn keeps_state() {
+        assert_eq!(
+            repl("x = 2\nfunc double(n) { return n * 2 }\ndouble(x) + 1\n\"a\"\n"),
+            "5\n\"a\"\n\n"
+        );
+    }
+
+    #[test]
+    fn multi_line_input() {
+        assert_eq!(
+            repl("func f(n) {\n  if n > 1 {\n    return n\n  }\n  return 0\n}\n[f(3),\nf(1)]\n"),
+            "[3, 0]\n\n"
+        );
+        assert_eq!(repl("x = \"a\nb\"\nx\n"), "\"a\\nb\"\n\n");
+    }
+
+    #[test]
+    fn errors_dont_exit() {
+        let output = repl("y + 1\n(1\n\ny = 1\ny + 1\n");
+        assert!(output.contains("error[E0201]"));
+        assert!(output.contains("error[E0103]"));
+        assert!(output.ends_with("2\n\n"));
+    }
+
+    #[test]
+    fn commands() {
+        assert!(repl(":tokens x + 1\n").contains("Identifier"));
+        assert!(repl(":ast x + 1\n").contains("__add"));
+        assert!(repl(":nope\n").contains("Unknown command"));
+        assert_eq!(repl(":quit\n1\n"), "");
+    }
+
+    #[test]
+    fn prints() {
+        assert_eq!(repl("print(1) 2\n"), "1\n2\n\n");
+        // what was printed before an error is kept
+        assert!(repl("print(\"a\") z\n").starts_with("a\nerror[E0201]"));
+    }
+}
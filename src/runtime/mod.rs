@@ -1,67 +1,235 @@
 pub mod objects;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
 
-use crate::libkody::GLOBALS;
-use crate::syntax_tree::{KodyNode, KodySyntaxTree};
-use objects::{KodyObject, KodyValue};
+use crate::error::KodyError;
+use crate::libkody::{
+    get_global, get_index, get_member, iterate, set_index, set_member, Context, GLOBALS,
+};
+use crate::span::Span;
+use crate::syntax_tree::{IfBranch, KodyFunctionData, KodyNode, KodySyntaxTree, Operation};
+use objects::{Captured, KodyFunction, KodyObject, KodyValue, NativeFunction};
 
-pub fn execute(syntax_tree: &KodySyntaxTree) -> Result<KodyObject, String> {
-    // start with global variables
-    let mut variable_stack = VariableStack::new(syntax_tree.global_variables.clone()); 
+// Where print and the other natives that produce output write to.
+// It is shared so that the host can read what was written.
+pub type Output = Rc<RefCell<dyn Write>>;
+
+pub fn standard_output() -> Output {
+    Rc::new(RefCell::new(io::stdout()))
+}
+
+pub fn execute(syntax_tree: &KodySyntaxTree, output: Output) -> Result<KodyObject, KodyError> {
+    let mut variable_stack = VariableStack::new(Scope::new(None), output);
 
     execute_node(&syntax_tree.main, &mut variable_stack)?;
 
-    Ok(variable_stack.return_value.unwrap_or_else(KodyObject::new))
+    Ok(variable_stack.return_value.unwrap_or_default())
+}
+
+// The state of an interactive session. Unlike in a program, statements run
+// directly in the global scope so that variables outlive the input that
+// defined them.
+pub struct Session {
+    variable_stack: VariableStack,
+    global_scope: Rc<RefCell<Scope>>,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        let global_scope = Scope::new(None);
+        Session {
+            variable_stack: VariableStack::new(global_scope.clone(), standard_output()),
+            global_scope,
+        }
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.variable_stack.output = output;
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<KodyObject> {
+        self.global_scope.borrow().variables.get(name).cloned()
+    }
+
+    pub fn set_global(&mut self, name: &str, value: KodyObject) {
+        self.global_scope
+            .borrow_mut()
+            .variables
+            .insert(name.to_string(), value);
+    }
+
+    // functions defined anywhere in the input can be called by all of it
+    pub fn declare_functions(&mut self, statements: &[KodyNode]) {
+        declare_functions(statements, &mut self.variable_stack);
+    }
+
+    pub fn execute_statement(&mut self, statement: &KodyNode) -> Result<KodyObject, KodyError> {
+        // a return outside of a function only ends the statement
+        self.run_statement(statement)
+            .map(|(value, return_value)| return_value.unwrap_or(value))
+    }
+
+    // Runs statements until one of them returns. The result is the returned
+    // value or the value of the last statement.
+    pub fn execute_statements(&mut self, statements: &[KodyNode]) -> Result<KodyObject, KodyError> {
+        self.declare_functions(statements);
+        let mut last_value = KodyObject::new();
+        for statement in statements {
+            let (value, return_value) = self.run_statement(statement)?;
+            if let Some(return_value) = return_value {
+                return Ok(return_value);
+            }
+            last_value = value;
+        }
+        Ok(last_value)
+    }
+
+    fn run_statement(
+        &mut self,
+        statement: &KodyNode,
+    ) -> Result<(KodyObject, Option<KodyObject>), KodyError> {
+        let result = execute_node(statement, &mut self.variable_stack);
+        let return_value = self.variable_stack.return_value.take();
+        // an error can leave the scope of a code block open
+        self.variable_stack.scope = self.global_scope.clone();
+        Ok((result?, return_value))
+    }
+
+    // Calls a function for the host program. The errors of the call itself
+    // don't come from the source code, so they have no span.
+    pub fn call(
+        &mut self,
+        function: &KodyObject,
+        arguments: Vec<KodyObject>,
+    ) -> Result<KodyObject, KodyError> {
+        let span = Span::default();
+        call_function(function, arguments, &self.variable_stack.context(span)).map_err(|error| {
+            if error.span() == Some(span) {
+                error.without_span()
+            } else {
+                error
+            }
+        })
+    }
+}
+
+// The variables of a code block or a function call. Functions keep the scope
+// they were defined in alive, so its variables can outlive the code block.
+pub struct Scope {
+    variables: HashMap<String, KodyObject>,
+    parent: Option<Rc<RefCell<Scope>>>,
+}
+
+impl Scope {
+    fn new(parent: Option<Rc<RefCell<Scope>>>) -> Rc<RefCell<Scope>> {
+        Rc::new(RefCell::new(Scope {
+            variables: HashMap::new(),
+            parent,
+        }))
+    }
 }
 
 struct VariableStack {
-    closures: Vec<HashMap<String, KodyObject>>,
+    // the innermost scope, the outer ones are reached through its parents
+    scope: Rc<RefCell<Scope>>,
     // this will be set to Some(value) when a function returns
     // and then passed through the stack
     return_value: Option<KodyObject>,
+    // set by break and continue and passed through the stack
+    // like return_value until it reaches the loop
+    loop_control: Option<LoopControl>,
+    output: Output,
+    // the number of function calls the code runs in
+    depth: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LoopControl {
+    Break,
+    Continue,
 }
 
 impl VariableStack {
-    pub fn new(global_variables: HashMap<String, KodyObject>) -> VariableStack {
+    pub fn new(scope: Rc<RefCell<Scope>>, output: Output) -> VariableStack {
         VariableStack {
-            closures: vec![global_variables],
+            scope,
             return_value: None,
+            loop_control: None,
+            output,
+            depth: 0,
+        }
+    }
+
+    // for the native functions called by the code, and the functions they call
+    fn context(&self, span: Span) -> TreeWalkerContext {
+        TreeWalkerContext {
+            span,
+            error: None,
+            output: self.output.clone(),
+            depth: self.depth,
         }
     }
-    // TODO is there a better way to do this?
+
+    // whether a return, break or continue is leaving the code being executed
+    pub fn unwinding(&self) -> bool {
+        self.return_value.is_some() || self.loop_control.is_some()
+    }
+
     pub fn open_closure(&mut self) {
-        self.closures.push(HashMap::new());
+        self.scope = Scope::new(Some(self.scope.clone()));
     }
 
     pub fn close_closure(&mut self) {
-        self.closures.pop();
+        let parent = self.scope.borrow().parent.clone();
+        if let Some(parent) = parent {
+            self.scope = parent;
+        }
     }
+
+    // create a variable in the innermost scope
+    pub fn define(&mut self, name: &str, value: KodyObject) {
+        self.scope
+            .borrow_mut()
+            .variables
+            .insert(name.to_string(), value);
+    }
+
     pub fn set(&mut self, name: &str, new_value: KodyObject) {
-        for closure in self.closures.iter_mut().rev() {
-            if let Some(value) = closure.get_mut(name) {
+        let mut scope = self.scope.clone();
+        loop {
+            if let Some(value) = scope.borrow_mut().variables.get_mut(name) {
                 *value = new_value;
                 return;
             }
+            let parent = scope.borrow().parent.clone();
+            match parent {
+                Some(parent) => scope = parent,
+                None => break,
+            }
         }
 
         // If no variable was found, create a new one
-        // TODO is it safe to unwrap
-        self.closures
-            .last_mut()
-            .unwrap()
-            .insert(name.to_string(), new_value);
+        self.define(name, new_value);
     }
 
     pub fn get(&self, name: &str) -> Result<KodyObject, String> {
-        for closure in self.closures.iter().rev() {
-            if let Some(value) = closure.get(name) {
+        let mut scope = self.scope.clone();
+        loop {
+            if let Some(value) = scope.borrow().variables.get(name) {
                 return Ok(value.clone());
             }
+            let parent = scope.borrow().parent.clone();
+            match parent {
+                Some(parent) => scope = parent,
+                None => break,
+            }
         }
 
-        if let Some(value) = GLOBALS.get(name) {
-            return Ok(value.clone());
+        if let Some(value) = get_global(name) {
+            return Ok(value);
         }
 
         Err(format!(
@@ -71,69 +239,225 @@ impl VariableStack {
     }
 }
 
-fn execute_node(node: &KodyNode, variable_stack: &mut VariableStack) -> Result<KodyObject, String> {
-    // propagate return value
-    if variable_stack.return_value.is_some() {
+// Function definitions are hoisted to the start of their code block, so the
+// functions of a block can call each other in any order. Each function
+// captures the scope of the block.
+fn declare_functions(statements: &[KodyNode], variable_stack: &mut VariableStack) {
+    for statement in statements {
+        if let KodyNode::FunctionDefinition { function, .. } = statement {
+            let value = make_function(function, variable_stack);
+            variable_stack.define(&function.name, value);
+        }
+    }
+}
+
+// a function that captures the current scope
+fn make_function(function: &Rc<KodyFunctionData>, variable_stack: &VariableStack) -> KodyObject {
+    KodyObject::from(KodyValue::Function(Rc::new(KodyFunction {
+        data: function.clone(),
+        captured: Captured::Scope(variable_stack.scope.clone()),
+    })))
+}
+
+fn execute_node(
+    node: &KodyNode,
+    variable_stack: &mut VariableStack,
+) -> Result<KodyObject, KodyError> {
+    with_stack(|| execute_node_here(node, variable_stack))
+}
+
+fn execute_node_here(
+    node: &KodyNode,
+    variable_stack: &mut VariableStack,
+) -> Result<KodyObject, KodyError> {
+    // propagate return value, break and continue
+    if variable_stack.unwinding() {
         return Ok(KodyObject::new());
     }
 
     match node {
-        KodyNode::CodeBlock { statements } => return execute_codeblock(statements, variable_stack),
-        KodyNode::GetConstant { value } => return Ok(value.clone()),
-        KodyNode::GetVariable { name } => return variable_stack.get(name),
-        KodyNode::SetVariable { name, value } => {
+        KodyNode::CodeBlock { statements, .. } => {
+            return execute_codeblock(statements, variable_stack)
+        }
+        KodyNode::GetConstant { value, .. } => return Ok(value.clone()),
+        KodyNode::GetVariable { name, span } => {
+            return variable_stack
+                .get(name)
+                .map_err(|error| KodyError::runtime("E0201", error, *span))
+        }
+        KodyNode::SetVariable { name, value, .. } => {
             let value_object = execute_node(value, variable_stack)?;
+            if variable_stack.unwinding() {
+                return Ok(KodyObject::new());
+            }
             variable_stack.set(name, value_object);
         }
         KodyNode::IfStatement {
-            condition,
-            action,
+            branches,
             else_action,
-        } => return execute_if_statement(condition, action, else_action, variable_stack),
-        KodyNode::WhileStatement { condition, action } => {
-            return execute_while_statement(condition, action, variable_stack)
-        }
-        KodyNode::ReturnFromFunction { return_value } => {
-            variable_stack.return_value = Some(execute_node(return_value, variable_stack)?)
+            ..
+        } => return execute_if_statement(branches, else_action, variable_stack),
+        KodyNode::WhileStatement {
+            condition, action, ..
+        } => return execute_while_statement(condition, action, variable_stack),
+        KodyNode::ForStatement {
+            variable,
+            iterable,
+            action,
+            ..
+        } => return execute_for_statement(variable, iterable, action, variable_stack),
+        KodyNode::ReturnFromFunction { return_value, .. } => {
+            let return_value = execute_node(return_value, variable_stack)?;
+            if !variable_stack.unwinding() {
+                variable_stack.return_value = Some(return_value);
+            }
         }
+        KodyNode::Break { .. } => variable_stack.loop_control = Some(LoopControl::Break),
+        KodyNode::Continue { .. } => variable_stack.loop_control = Some(LoopControl::Continue),
         KodyNode::CallFunction {
             function,
             arguments,
-        } => return execute_function_call(function, arguments, variable_stack),
+            span,
+        } => return execute_function_call(function, arguments, *span, variable_stack),
+        KodyNode::OperatorChain {
+            first, operations, ..
+        } => return execute_operator_chain(first, operations, variable_stack),
+        KodyNode::GetMember { base, member, span } => {
+            let base = execute_node(base, variable_stack)?;
+            if variable_stack.unwinding() {
+                return Ok(KodyObject::new());
+            }
+            return get_member(&base, member).map_err(|error| member_error(error, *span));
+        }
+        KodyNode::SetMember {
+            base,
+            member,
+            operation,
+            value,
+            span,
+        } => execute_set_member(base, member, *operation, value, *span, variable_stack)?,
+        KodyNode::GetIndex { base, index, span } => {
+            return execute_get_index(base, index, *span, variable_stack)
+        }
+        KodyNode::SetIndex {
+            base,
+            index,
+            value,
+            span,
+        } => execute_set_index(base, index, None, value, *span, variable_stack)?,
+        KodyNode::UpdateIndex {
+            base,
+            index,
+            operation,
+            value,
+            span,
+        } => execute_set_index(base, index, Some(*operation), value, *span, variable_stack)?,
+        // the code block around the definition has already declared the function
+        KodyNode::FunctionDefinition { .. } => (),
+        KodyNode::Lambda { function, .. } => return Ok(make_function(function, variable_stack)),
     }
     Ok(KodyObject::new())
 }
 
+fn execute_set_member(
+    base: &KodyNode,
+    member: &str,
+    operation: Option<&str>,
+    value: &KodyNode,
+    span: Span,
+    variable_stack: &mut VariableStack,
+) -> Result<(), KodyError> {
+    let base = execute_node(base, variable_stack)?;
+    let value = execute_node(value, variable_stack)?;
+    if variable_stack.unwinding() {
+        return Ok(());
+    }
+    let mut context = variable_stack.context(span);
+    assign_member(&mut context, &base, member, operation, value, span)
+}
+
+fn execute_get_index(
+    base: &KodyNode,
+    index: &KodyNode,
+    span: Span,
+    variable_stack: &mut VariableStack,
+) -> Result<KodyObject, KodyError> {
+    let base = execute_node(base, variable_stack)?;
+    let index = execute_node(index, variable_stack)?;
+    if variable_stack.unwinding() {
+        return Ok(KodyObject::new());
+    }
+    get_index(&base, &index).map_err(|error| index_error(error, span))
+}
+
+// xs[i] = value, or xs[i] += value and the like with an operation
+fn execute_set_index(
+    base: &KodyNode,
+    index: &KodyNode,
+    operation: Option<&str>,
+    value: &KodyNode,
+    span: Span,
+    variable_stack: &mut VariableStack,
+) -> Result<(), KodyError> {
+    let base = execute_node(base, variable_stack)?;
+    let index = execute_node(index, variable_stack)?;
+    let value = execute_node(value, variable_stack)?;
+    if variable_stack.unwinding() {
+        return Ok(());
+    }
+    match operation {
+        Some(operation) => {
+            let mut context = variable_stack.context(span);
+            update_index(&mut context, &base, &index, operation, value, span)
+        }
+        None => set_index(&base, &index, value).map_err(|error| index_error(error, span)),
+    }
+}
+
 fn execute_codeblock(
     statements: &[KodyNode],
     variable_stack: &mut VariableStack,
-) -> Result<KodyObject, String> {
+) -> Result<KodyObject, KodyError> {
     // add a new closure
-    variable_stack.open_closure(); 
+    variable_stack.open_closure();
+    declare_functions(statements, variable_stack);
 
     // execute every statement
     for statement in statements {
-        execute_node(statement, variable_stack)?; 
+        execute_node(statement, variable_stack)?;
     }
 
     // delete variables from closure
-    variable_stack.close_closure(); 
+    variable_stack.close_closure();
     Ok(KodyObject::new())
 }
 
 fn execute_if_statement(
-    condition: &KodyNode,
-    action: &KodyNode,
+    branches: &[IfBranch],
     else_action: &Option<Box<KodyNode>>,
     variable_stack: &mut VariableStack,
-) -> Result<KodyObject, String> {
-    if match *execute_node(condition, variable_stack)?.value {
-        KodyValue::Bool(true) => true,
-        KodyValue::Bool(false) => false,
-        _ => return Err(String::from("Object in if condition was not a bool!")),
-    } {
-        execute_node(action, variable_stack)?;
-    } else if let Some(node) = else_action {
+) -> Result<KodyObject, KodyError> {
+    for branch in branches {
+        let condition_value = execute_node(&branch.condition, variable_stack)?;
+        if variable_stack.unwinding() {
+            return Ok(KodyObject::new());
+        }
+        match *condition_value.value {
+            KodyValue::Bool(true) => {
+                execute_node(&branch.action, variable_stack)?;
+                return Ok(KodyObject::new());
+            }
+            KodyValue::Bool(false) => (),
+            _ => {
+                return Err(KodyError::runtime(
+                    "E0202",
+                    "Object in if condition was not a bool!",
+                    branch.condition.span(),
+                ))
+            }
+        }
+    }
+    if let Some(node) = else_action {
         execute_node(node, variable_stack)?;
     }
 
@@ -144,13 +468,73 @@ fn execute_while_statement(
     condition: &KodyNode,
     action: &KodyNode,
     variable_stack: &mut VariableStack,
-) -> Result<KodyObject, String> {
-    while match *execute_node(condition, variable_stack)?.value {
-        KodyValue::Bool(true) => true,
-        KodyValue::Bool(false) => false,
-        _ => return Err(String::from("Object in while condition was not a bool!")),
-    } {
+) -> Result<KodyObject, KodyError> {
+    loop {
+        let condition_value = execute_node(condition, variable_stack)?;
+        // a break or continue in the condition belongs to an outer loop
+        if variable_stack.unwinding() {
+            break;
+        }
+        match *condition_value.value {
+            KodyValue::Bool(true) => (),
+            KodyValue::Bool(false) => break,
+            _ => {
+                return Err(KodyError::runtime(
+                    "E0202",
+                    "Object in while condition was not a bool!",
+                    condition.span(),
+                ))
+            }
+        }
+
+        execute_node(action, variable_stack)?;
+
+        // stop looping when returning from a function
+        if variable_stack.return_value.is_some()
+            || variable_stack.loop_control.take() == Some(LoopControl::Break)
+        {
+            break;
+        }
+    }
+
+    Ok(KodyObject::new())
+}
+
+fn execute_for_statement(
+    variable: &str,
+    iterable: &KodyNode,
+    action: &KodyNode,
+    variable_stack: &mut VariableStack,
+) -> Result<KodyObject, KodyError> {
+    let iterable_value = execute_node(iterable, variable_stack)?;
+    if variable_stack.unwinding() {
+        return Ok(KodyObject::new());
+    }
+    let span = iterable.span();
+    let iterator = iterate(&iterable_value).map_err(|error| iteration_error(error, span))?;
+
+    loop {
+        let mut context = variable_stack.context(span);
+        let item = next_item(&iterator, &mut context)
+            .map_err(|error| native_error(error, context.error, span))?;
+        let item = match item {
+            Some(item) => item,
+            None => break,
+        };
+
+        // every iteration has its own variable, so the functions
+        // made in one iteration don't see the items of the next ones
+        variable_stack.open_closure();
+        variable_stack.define(variable, item);
         execute_node(action, variable_stack)?;
+        variable_stack.close_closure();
+
+        // stop looping when returning from a function
+        if variable_stack.return_value.is_some()
+            || variable_stack.loop_control.take() == Some(LoopControl::Break)
+        {
+            break;
+        }
     }
 
     Ok(KodyObject::new())
@@ -159,48 +543,254 @@ fn execute_while_statement(
 fn execute_function_call(
     function: &KodyNode,
     arguments: &[KodyNode],
+    span: Span,
     variable_stack: &mut VariableStack,
-) -> Result<KodyObject, String> {
-    if let KodyValue::Function(func_data) = *execute_node(function, variable_stack)?.value {
-        if func_data.arguments.len() != arguments.len() {
-            return Err(String::from(
-                "Different number of arguments in function definition and function call!",
-            ));
-        }
+) -> Result<KodyObject, KodyError> {
+    let function_object = execute_node(function, variable_stack)?;
 
-        let mut argument_objects = Vec::new();
+    let mut argument_objects = Vec::with_capacity(arguments.len() + 1);
+    for arg in arguments {
+        argument_objects.push(execute_node(arg, variable_stack)?);
+    }
+    // a break, continue or return in the arguments skips the call
+    if variable_stack.unwinding() {
+        return Ok(KodyObject::new());
+    }
+
+    call_function(
+        &function_object,
+        argument_objects,
+        &variable_stack.context(span),
+    )
+}
 
-        for arg in arguments {
-            argument_objects.push(execute_node(arg, variable_stack)?);
+// the operations run one after the other, a long chain doesn't recurse
+fn execute_operator_chain(
+    first: &KodyNode,
+    operations: &[Operation],
+    variable_stack: &mut VariableStack,
+) -> Result<KodyObject, KodyError> {
+    let mut value = execute_node(first, variable_stack)?;
+    for operation in operations {
+        let function = variable_stack
+            .get(operation.operator)
+            .map_err(|error| KodyError::runtime("E0201", error, operation.span))?;
+        let operand = execute_node(&operation.operand, variable_stack)?;
+        if variable_stack.unwinding() {
+            return Ok(KodyObject::new());
         }
+        value = call_function(
+            &function,
+            vec![value, operand],
+            &variable_stack.context(operation.span),
+        )?;
+    }
+    Ok(value)
+}
 
-        let mut new_globals = variable_stack.closures[0].clone();
-        new_globals.extend(
-            func_data
-                .arguments
-                .iter()
-                .zip(argument_objects.iter())
-                .map(|(name, object)| (name.clone(), object.clone())),
-        );
+// calls a function from the code or the native function the context belongs to
+fn call_function(
+    function_object: &KodyObject,
+    mut argument_objects: Vec<KodyObject>,
+    context: &TreeWalkerContext,
+) -> Result<KodyObject, KodyError> {
+    let span = context.span;
+    match function_object.value.as_ref() {
+        KodyValue::Function(function) => {
+            let func_data = &function.data;
+            if func_data.arguments.len() != argument_objects.len() {
+                return Err(arity_error(func_data, argument_objects.len(), span));
+            }
+            let scope = match &function.captured {
+                Captured::Scope(scope) => scope.clone(),
+                Captured::Variables { .. } => return Err(foreign_function_error(span)),
+            };
 
-        let tree = KodySyntaxTree {
-            global_variables: new_globals,
-            main: func_data.body,
-        };
+            if context.depth >= MAX_CALL_DEPTH {
+                return Err(recursion_error(span));
+            }
 
-        execute(&tree)
-    } else if let KodyValue::NativeFunction(function) =
-        *execute_node(function, variable_stack)?.value
-    {
-        let mut argument_objects = Vec::new();
-        for arg in arguments {
-            argument_objects.push(execute_node(arg, variable_stack)?);
+            // the function can see the scope it was defined in and its own arguments
+            let mut function_stack =
+                VariableStack::new(Scope::new(Some(scope)), context.output.clone());
+            function_stack.depth = context.depth + 1;
+            for (name, value) in func_data.arguments.iter().zip(argument_objects) {
+                function_stack.define(name, value);
+            }
+
+            execute_node(&func_data.body, &mut function_stack)?;
+
+            Ok(function_stack.return_value.unwrap_or_default())
+        }
+        KodyValue::NativeFunction(function) => call_native(function, argument_objects, context),
+        KodyValue::Method { receiver, function } => {
+            argument_objects.insert(0, receiver.clone());
+            call_native(function, argument_objects, context)
         }
+        _ => Err(not_callable_error(span)),
+    }
+}
 
-        function(argument_objects)
-    } else {
-        Err(String::from(
-            "Cannot make function call with value other than a function",
-        ))
+// Lets native functions call functions of the program
+struct TreeWalkerContext {
+    // the span of the native function call, for the errors of the calls it makes
+    span: Span,
+    error: Option<KodyError>,
+    output: Output,
+    depth: usize,
+}
+
+impl Context for TreeWalkerContext {
+    fn call(
+        &mut self,
+        function: &KodyObject,
+        arguments: Vec<KodyObject>,
+    ) -> Result<KodyObject, String> {
+        call_function(function, arguments, self).map_err(|error| {
+            let message = error.message().to_string();
+            self.error = Some(error);
+            message
+        })
     }
+
+    fn write_output(&mut self, text: &str) -> Result<(), String> {
+        write_output(&self.output, text)
+    }
+}
+
+fn call_native(
+    function: &NativeFunction,
+    arguments: Vec<KodyObject>,
+    context: &TreeWalkerContext,
+) -> Result<KodyObject, KodyError> {
+    let mut context = TreeWalkerContext {
+        error: None,
+        output: context.output.clone(),
+        ..*context
+    };
+    function
+        .call(&mut context, arguments)
+        .map_err(|error| native_error(error, context.error, context.span))
+}
+
+// Deep recursion would use up the memory of the interpreter, so the calls
+// that functions make are limited
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+// The tree walker recurses for every function call of the program and the
+// vm for every function that a native function calls, with about 20KB of
+// stack for a call of the tree walker in a debug build. Instead of
+// overflowing the stack of the thread, whatever its size, f gets a new
+// piece of stack when little is left.
+pub fn with_stack<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(64 * 1024, 1024 * 1024, f)
+}
+
+pub fn recursion_error(span: Span) -> KodyError {
+    KodyError::runtime("E0209", "Maximum recursion depth exceeded!", span).with_note(format!(
+        "functions can only be nested {} calls deep",
+        MAX_CALL_DEPTH
+    ))
+}
+
+pub fn iteration_error(error: String, span: Span) -> KodyError {
+    KodyError::runtime("E0208", error, span)
+}
+
+// the next item of a value made by iterate
+pub fn next_item(
+    iterator: &KodyObject,
+    context: &mut dyn Context,
+) -> Result<Option<KodyObject>, String> {
+    match iterator.value.as_ref() {
+        KodyValue::Iterator(iterator) => iterator.next_item(context),
+        _ => Ok(None),
+    }
+}
+
+pub fn write_output(output: &Output, text: &str) -> Result<(), String> {
+    output
+        .borrow_mut()
+        .write_all(text.as_bytes())
+        .map_err(|error| format!("Cannot write the output: {}!", error))
+}
+
+// a native function failed, either by itself or because a function it called did
+pub fn native_error(error: String, call_error: Option<KodyError>, span: Span) -> KodyError {
+    call_error.unwrap_or_else(|| KodyError::runtime("E0205", error, span))
+}
+
+pub fn not_callable_error(span: Span) -> KodyError {
+    KodyError::runtime(
+        "E0204",
+        "Cannot make function call with value other than a function",
+        span,
+    )
+}
+
+// a function made by the other backend, whose captured variables can't be read
+pub fn foreign_function_error(span: Span) -> KodyError {
+    KodyError::runtime(
+        "E0204",
+        "Cannot call a function that was created by another backend",
+        span,
+    )
+}
+
+pub fn index_error(error: String, span: Span) -> KodyError {
+    KodyError::runtime("E0206", error, span)
+}
+
+// xs[i] += value and the like, operation is the native function to apply
+pub fn update_index(
+    context: &mut dyn Context,
+    base: &KodyObject,
+    index: &KodyObject,
+    operation: &str,
+    value: KodyObject,
+    span: Span,
+) -> Result<(), KodyError> {
+    let old_value = get_index(base, index).map_err(|error| index_error(error, span))?;
+    // the parser only creates operations that exist
+    let new_value = GLOBALS[operation](context, vec![old_value, value])
+        .map_err(|error| KodyError::runtime("E0205", error, span))?;
+    set_index(base, index, new_value).map_err(|error| index_error(error, span))
+}
+
+pub fn member_error(error: String, span: Span) -> KodyError {
+    KodyError::runtime("E0207", error, span)
+}
+
+// obj.member = value, or obj.member += value and the like
+pub fn assign_member(
+    context: &mut dyn Context,
+    base: &KodyObject,
+    member: &str,
+    operation: Option<&str>,
+    value: KodyObject,
+    span: Span,
+) -> Result<(), KodyError> {
+    let value = match operation {
+        Some(operation) => {
+            let old_value = get_member(base, member).map_err(|error| member_error(error, span))?;
+            GLOBALS[operation](context, vec![old_value, value])
+                .map_err(|error| KodyError::runtime("E0205", error, span))?
+        }
+        None => value,
+    };
+    set_member(base, member, value).map_err(|error| member_error(error, span))
+}
+
+pub fn arity_error(func_data: &KodyFunctionData, argument_count: usize, span: Span) -> KodyError {
+    KodyError::runtime(
+        "E0203",
+        "Different number of arguments in function definition and function call!",
+        span,
+    )
+    .with_note(format!(
+        "{} takes {} argument(s) but {} were given",
+        func_data.name,
+        func_data.arguments.len(),
+        argument_count
+    ))
 }
@@ -0,0 +1,134 @@
+// Equality and ordering of values as kody sees them, used by the
+// comparison operators and by sorting.
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::KodyValue;
+
+// the lists or maps being compared with each other
+type Pairs = Vec<(*const KodyValue, *const KodyValue)>;
+
+impl KodyValue {
+    // Lists and maps are equal when their items are, functions and
+    // iterators only when they are the same one. Values of different
+    // types are never equal.
+    pub fn equals(&self, other: &KodyValue) -> bool {
+        self.equals_in(other, &mut vec![])
+    }
+
+    // A list can contain itself, so a pair that is compared again while
+    // it is being compared is taken to be equal. If anything else in it
+    // differs, that is found elsewhere.
+    fn equals_in(&self, other: &KodyValue, compared: &mut Pairs) -> bool {
+        let pair = (self as *const KodyValue, other as *const KodyValue);
+        if pair.0 == pair.1 || compared.contains(&pair) {
+            return true;
+        }
+        match (self, other) {
+            (KodyValue::Bool(a), KodyValue::Bool(b)) => a == b,
+            (KodyValue::Number(a), KodyValue::Number(b)) => a == b,
+            (KodyValue::StringLiteral(a), KodyValue::StringLiteral(b)) => a == b,
+            (KodyValue::Empty, KodyValue::Empty) => true,
+            (KodyValue::List(a), KodyValue::List(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                if a.len() != b.len() {
+                    return false;
+                }
+                compared.push(pair);
+                let equal = a
+                    .iter()
+                    .zip(b.iter())
+                    .all(|(x, y)| x.value.equals_in(&y.value, compared));
+                compared.pop();
+                equal
+            }
+            // the order of the keys doesn't matter
+            (KodyValue::Map(a), KodyValue::Map(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                if a.len() != b.len() {
+                    return false;
+                }
+                compared.push(pair);
+                let equal = a.iter().all(|(key, x)| {
+                    b.get(key)
+                        .is_some_and(|y| x.value.equals_in(&y.value, compared))
+                });
+                compared.pop();
+                equal
+            }
+            (KodyValue::Function(a), KodyValue::Function(b)) => Rc::ptr_eq(a, b),
+            (KodyValue::NativeFunction(a), KodyValue::NativeFunction(b)) => a == b,
+            (
+                KodyValue::Method {
+                    receiver: a,
+                    function: f,
+                },
+                KodyValue::Method {
+                    receiver: b,
+                    function: g,
+                },
+            ) => Rc::ptr_eq(&a.value, &b.value) && f == g,
+            (KodyValue::Iterator(a), KodyValue::Iterator(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // Numbers are ordered by value and strings lexicographically by their
+    // characters. None if the values can't be ordered.
+    pub fn compare(&self, other: &KodyValue) -> Option<Ordering> {
+        match (self, other) {
+            (KodyValue::Number(a), KodyValue::Number(b)) => Some(a.cmp(b)),
+            (KodyValue::StringLiteral(a), KodyValue::StringLiteral(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::runtime::objects::{IntoKody, KodyObject};
+
+    #[test]
+    fn equality() {
+        let equals = |a: KodyObject, b: KodyObject| a.value.equals(&b.value);
+        assert!(equals("a".into_kody(), "a".into_kody()));
+        assert!(!equals(true.into_kody(), false.into_kody()));
+        assert!(equals(KodyObject::new(), KodyObject::new()));
+        assert!(!equals(1.into_kody(), "1".into_kody()));
+        assert!(!equals(KodyObject::new(), false.into_kody()));
+        assert!(equals(vec![1, 2].into_kody(), vec![1, 2].into_kody()));
+        assert!(!equals(vec![1, 2].into_kody(), vec![1].into_kody()));
+
+        // lists that contain themselves
+        let cycle = || {
+            let list = KodyObject::from(KodyValue::List(RefCell::new(vec![])));
+            if let KodyValue::List(items) = list.value.as_ref() {
+                items.borrow_mut().push(list.clone());
+            }
+            list
+        };
+        assert!(equals(cycle(), cycle()));
+        let list = cycle();
+        assert!(equals(list.clone(), list));
+    }
+
+    #[test]
+    fn ordering() {
+        let compare = |a: KodyObject, b: KodyObject| a.value.compare(&b.value);
+        assert_eq!(compare(1.into_kody(), 2.into_kody()), Some(Ordering::Less));
+        assert_eq!(
+            compare("b".into_kody(), "abc".into_kody()),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare("Z".into_kody(), "ä".into_kody()),
+            Some(Ordering::Less)
+        );
+        assert_eq!(compare(true.into_kody(), false.into_kody()), None);
+        assert_eq!(compare(1.into_kody(), "1".into_kody()), None);
+    }
+}
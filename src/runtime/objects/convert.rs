@@ -0,0 +1,432 @@
+// Conversions between Rust values and kody values. Natives declare the Rust
+// types of their arguments and the conversions produce the argument count
+// and type errors for them.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+use super::{KodyMap, KodyNumber, KodyObject, KodyValue, MapKey};
+
+// A Rust value that can be read from a kody object. The error says
+// what was expected and what was found instead.
+pub trait FromKody: Sized {
+    fn from_kody(object: &KodyObject) -> Result<Self, String>;
+}
+
+// A Rust value that can be turned into a kody object
+pub trait IntoKody {
+    fn into_kody(self) -> KodyObject;
+}
+
+impl FromKody for KodyObject {
+    fn from_kody(object: &KodyObject) -> Result<KodyObject, String> {
+        Ok(object.clone())
+    }
+}
+
+impl IntoKody for KodyObject {
+    fn into_kody(self) -> KodyObject {
+        self
+    }
+}
+
+// everything that can be read from a value can be read from an object
+impl<T> FromKody for T
+where
+    T: for<'a> TryFrom<&'a KodyValue, Error = String>,
+{
+    fn from_kody(object: &KodyObject) -> Result<T, String> {
+        T::try_from(object.value.as_ref())
+    }
+}
+
+impl<T> IntoKody for T
+where
+    KodyValue: From<T>,
+{
+    fn into_kody(self) -> KodyObject {
+        KodyObject::from(KodyValue::from(self))
+    }
+}
+
+fn type_error(expected: &str, value: &KodyValue) -> String {
+    format!(
+        "expected {} but got {}",
+        expected,
+        value.type_name_with_article()
+    )
+}
+
+impl From<()> for KodyValue {
+    fn from(_: ()) -> KodyValue {
+        KodyValue::Empty
+    }
+}
+
+impl From<bool> for KodyValue {
+    fn from(x: bool) -> KodyValue {
+        KodyValue::Bool(x)
+    }
+}
+
+impl TryFrom<&KodyValue> for bool {
+    type Error = String;
+    fn try_from(value: &KodyValue) -> Result<bool, String> {
+        match value {
+            KodyValue::Bool(val) => Ok(*val),
+            other => Err(type_error("a bool", other)),
+        }
+    }
+}
+
+impl From<KodyNumber> for KodyValue {
+    fn from(x: KodyNumber) -> KodyValue {
+        KodyValue::Number(x)
+    }
+}
+
+impl TryFrom<&KodyValue> for KodyNumber {
+    type Error = String;
+    fn try_from(value: &KodyValue) -> Result<KodyNumber, String> {
+        match value {
+            KodyValue::Number(val) => Ok(val.clone()),
+            other => Err(type_error("a number", other)),
+        }
+    }
+}
+
+macro_rules! integer_conversions {
+    ($($integer:ty),*) => {
+        $(
+            impl From<$integer> for KodyValue {
+                fn from(x: $integer) -> KodyValue {
+                    KodyValue::Number(KodyNumber::from(x))
+                }
+            }
+
+            impl TryFrom<&KodyValue> for $integer {
+                type Error = String;
+                fn try_from(value: &KodyValue) -> Result<$integer, String> {
+                    let number = KodyNumber::try_from(value)?;
+                    number
+                        .to_i128()
+                        .and_then(|x| <$integer>::try_from(x).ok())
+                        .or_else(|| number.to_u128().and_then(|x| <$integer>::try_from(x).ok()))
+                        .ok_or_else(|| {
+                            format!(
+                                "expected an integer between {} and {} but got {}",
+                                <$integer>::MIN,
+                                <$integer>::MAX,
+                                number
+                            )
+                        })
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+// Floats only convert to numbers when they are finite and the numbers are
+// marked as approximate. The other way the closest float is used, so
+// precision can be lost.
+impl TryFrom<f64> for KodyValue {
+    type Error = String;
+    fn try_from(x: f64) -> Result<KodyValue, String> {
+        KodyNumber::approximate(x)
+            .map(KodyValue::Number)
+            .ok_or_else(|| format!("{} is not a number!", x))
+    }
+}
+
+impl TryFrom<&KodyValue> for f64 {
+    type Error = String;
+    fn try_from(value: &KodyValue) -> Result<f64, String> {
+        Ok(KodyNumber::try_from(value)?.to_f64())
+    }
+}
+
+impl From<String> for KodyValue {
+    fn from(x: String) -> KodyValue {
+        KodyValue::StringLiteral(x)
+    }
+}
+
+impl From<&str> for KodyValue {
+    fn from(x: &str) -> KodyValue {
+        KodyValue::StringLiteral(String::from(x))
+    }
+}
+
+impl TryFrom<&KodyValue> for String {
+    type Error = String;
+    fn try_from(value: &KodyValue) -> Result<String, String> {
+        match value {
+            KodyValue::StringLiteral(val) => Ok(val.clone()),
+            other => Err(type_error("a string", other)),
+        }
+    }
+}
+
+// Converting a list or a map copies it, changes on one side
+// are not seen on the other.
+impl<T: IntoKody> From<Vec<T>> for KodyValue {
+    fn from(items: Vec<T>) -> KodyValue {
+        KodyValue::List(RefCell::new(
+            items.into_iter().map(IntoKody::into_kody).collect(),
+        ))
+    }
+}
+
+impl<T: FromKody> TryFrom<&KodyValue> for Vec<T> {
+    type Error = String;
+    fn try_from(value: &KodyValue) -> Result<Vec<T>, String> {
+        match value {
+            KodyValue::List(items) => items.borrow().iter().map(T::from_kody).collect(),
+            other => Err(type_error("a list", other)),
+        }
+    }
+}
+
+// the entries of a HashMap have no order, so the keys of
+// the resulting map are in an arbitrary one
+impl<K: Into<MapKey>, V: IntoKody> From<HashMap<K, V>> for KodyValue {
+    fn from(entries: HashMap<K, V>) -> KodyValue {
+        let mut map = KodyMap::new();
+        for (key, value) in entries {
+            map.insert(key.into(), value.into_kody());
+        }
+        KodyValue::Map(RefCell::new(map))
+    }
+}
+
+impl<K: FromKody + Eq + Hash, V: FromKody> TryFrom<&KodyValue> for HashMap<K, V> {
+    type Error = String;
+    fn try_from(value: &KodyValue) -> Result<HashMap<K, V>, String> {
+        match value {
+            KodyValue::Map(map) => map
+                .borrow()
+                .iter()
+                .map(|(key, value)| Ok((K::from_kody(&key.to_object())?, V::from_kody(value)?)))
+                .collect(),
+            other => Err(type_error("a map", other)),
+        }
+    }
+}
+
+impl From<bool> for MapKey {
+    fn from(x: bool) -> MapKey {
+        MapKey::Bool(x)
+    }
+}
+
+impl From<String> for MapKey {
+    fn from(x: String) -> MapKey {
+        MapKey::StringLiteral(x)
+    }
+}
+
+impl From<&str> for MapKey {
+    fn from(x: &str) -> MapKey {
+        MapKey::StringLiteral(String::from(x))
+    }
+}
+
+impl<T: Into<KodyNumber>> From<T> for MapKey {
+    fn from(x: T) -> MapKey {
+        MapKey::Number(x.into())
+    }
+}
+
+// What a typed native returns: a value, or a Result when it can fail
+pub trait IntoKodyResult {
+    fn into_kody_result(self) -> Result<KodyObject, String>;
+}
+
+impl<T: IntoKody> IntoKodyResult for T {
+    fn into_kody_result(self) -> Result<KodyObject, String> {
+        Ok(self.into_kody())
+    }
+}
+
+impl<T: IntoKody> IntoKodyResult for Result<T, String> {
+    fn into_kody_result(self) -> Result<KodyObject, String> {
+        self.map(IntoKody::into_kody)
+    }
+}
+
+// a float result fails when it is NaN or infinite
+impl IntoKodyResult for f64 {
+    fn into_kody_result(self) -> Result<KodyObject, String> {
+        KodyValue::try_from(self).map(KodyObject::from)
+    }
+}
+
+impl IntoKodyResult for Result<f64, String> {
+    fn into_kody_result(self) -> Result<KodyObject, String> {
+        self?.into_kody_result()
+    }
+}
+
+pub fn check_argument_count(
+    args: &[KodyObject],
+    count: usize,
+    function_name: &str,
+) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!(
+            "{} takes {} argument(s) but {} were given!",
+            function_name,
+            count,
+            args.len()
+        ));
+    }
+    Ok(())
+}
+
+// The arguments of a native as a tuple of Rust values, such as
+// (KodyNumber, KodyNumber) for a native taking two numbers
+pub trait FromArguments: Sized {
+    fn from_arguments(args: &[KodyObject], function_name: &str) -> Result<Self, String>;
+}
+
+fn argument<T: FromKody>(
+    args: &[KodyObject],
+    index: usize,
+    function_name: &str,
+) -> Result<T, String> {
+    T::from_kody(&args[index])
+        .map_err(|error| format!("Argument {} of {}: {}!", index + 1, function_name, error))
+}
+
+pub fn from_arguments<T: FromArguments>(
+    args: &[KodyObject],
+    function_name: &str,
+) -> Result<T, String> {
+    T::from_arguments(args, function_name)
+}
+
+// Every argument of a native that takes any number of them, such as min
+pub fn from_each_argument<T: FromKody>(
+    args: &[KodyObject],
+    function_name: &str,
+) -> Result<Vec<T>, String> {
+    (0..args.len())
+        .map(|index| argument(args, index, function_name))
+        .collect()
+}
+
+// A Rust function or closure whose parameters and result convert
+// to and from kody values
+pub trait TypedFunction<Arguments> {
+    fn call_typed(&self, args: &[KodyObject], function_name: &str) -> Result<KodyObject, String>;
+}
+
+macro_rules! typed_arguments {
+    ($count:expr; $($name:ident $index:tt),*) => {
+        impl<$($name: FromKody),*> FromArguments for ($($name,)*) {
+            #[allow(unused_variables)]
+            fn from_arguments(args: &[KodyObject], function_name: &str) -> Result<Self, String> {
+                check_argument_count(args, $count, function_name)?;
+                Ok(($(argument::<$name>(args, $index, function_name)?,)*))
+            }
+        }
+
+        impl<F, R, $($name: FromKody),*> TypedFunction<($($name,)*)> for F
+        where
+            F: Fn($($name),*) -> R,
+            R: IntoKodyResult,
+        {
+            #[allow(non_snake_case)]
+            fn call_typed(&self, args: &[KodyObject], function_name: &str) -> Result<KodyObject, String> {
+                let ($($name,)*) = from_arguments::<($($name,)*)>(args, function_name)?;
+                self($($name),*).into_kody_result()
+            }
+        }
+    };
+}
+
+typed_arguments!(0;);
+typed_arguments!(1; A 0);
+typed_arguments!(2; A 0, B 1);
+typed_arguments!(3; A 0, B 1, C 2);
+typed_arguments!(4; A 0, B 1, C 2, D 3);
+typed_arguments!(5; A 0, B 1, C 2, D 3, E 4);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(x: i64) -> KodyObject {
+        x.into_kody()
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(i64::from_kody(&number(-3)), Ok(-3));
+        assert_eq!(
+            u8::from_kody(&number(300)),
+            Err(String::from(
+                "expected an integer between 0 and 255 but got 300"
+            ))
+        );
+        assert_eq!(
+            f64::from_kody(&KodyObject::from(KodyValue::try_from(0.25).unwrap())),
+            Ok(0.25)
+        );
+        assert_eq!(bool::from_kody(&true.into_kody()), Ok(true));
+        assert_eq!(
+            String::from_kody(&number(1)),
+            Err(String::from("expected a string but got a number"))
+        );
+        assert!(KodyValue::try_from(f64::NAN).is_err());
+        assert_eq!(u128::from_kody(&u128::MAX.into_kody()), Ok(u128::MAX));
+        assert_eq!(i128::from_kody(&i128::MIN.into_kody()), Ok(i128::MIN));
+        assert!(i128::from_kody(&u128::MAX.into_kody()).is_err());
+    }
+
+    #[test]
+    fn collections() {
+        let list = vec![1, 2, 3].into_kody();
+        assert_eq!(list.value.to_string(), "[1, 2, 3]");
+        assert_eq!(Vec::<i32>::from_kody(&list), Ok(vec![1, 2, 3]));
+        assert!(Vec::<String>::from_kody(&list).is_err());
+
+        let mut entries = HashMap::new();
+        entries.insert(String::from("a"), vec![true]);
+        let map = entries.clone().into_kody();
+        assert_eq!(map.value.to_string(), "{\"a\": [true]}");
+        assert_eq!(HashMap::<String, Vec<bool>>::from_kody(&map), Ok(entries));
+    }
+
+    #[test]
+    fn arguments() {
+        let add = |a: i64, b: i64| a + b;
+        assert_eq!(
+            add.call_typed(&[number(1), number(2)], "add"),
+            Ok(number(3))
+        );
+        assert_eq!(
+            add.call_typed(&[number(1)], "add"),
+            Err(String::from("add takes 2 argument(s) but 1 were given!"))
+        );
+        assert_eq!(
+            add.call_typed(&[number(1), "2".into_kody()], "add"),
+            Err(String::from(
+                "Argument 2 of add: expected a number but got a string!"
+            ))
+        );
+
+        let divide = |a: KodyNumber, b: KodyNumber| {
+            a.checked_div(&b)
+                .ok_or_else(|| String::from("Cannot divide by zero!"))
+        };
+        assert_eq!(
+            divide.call_typed(&[number(1), number(0)], "divide"),
+            Err(String::from("Cannot divide by zero!"))
+        );
+    }
+}
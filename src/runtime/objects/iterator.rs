@@ -0,0 +1,48 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use super::KodyObject;
+use crate::libkody::Context;
+
+// The iterator protocol. A for loop asks an iterator for items until
+// it has none left. The context lets an iterator call kody functions,
+// so that objects written in kody can become iterators too.
+pub trait Iterate {
+    fn next_item(&mut self, context: &mut dyn Context) -> Result<Option<KodyObject>, String>;
+}
+
+// An iterator is used up by iterating over it, so iterating over the
+// same iterator again only gives the items that are left.
+pub struct KodyIterator {
+    state: RefCell<Box<dyn Iterate>>,
+}
+
+impl KodyIterator {
+    pub fn new<T: Iterate + 'static>(state: T) -> KodyIterator {
+        KodyIterator {
+            state: RefCell::new(Box::new(state)),
+        }
+    }
+
+    pub fn next_item(&self, context: &mut dyn Context) -> Result<Option<KodyObject>, String> {
+        match self.state.try_borrow_mut() {
+            Ok(mut state) => state.next_item(context),
+            Err(_) => Err(String::from(
+                "Cannot use an iterator while it is giving its next item!",
+            )),
+        }
+    }
+}
+
+// an iterator is only equal to itself
+impl PartialEq for KodyIterator {
+    fn eq(&self, other: &KodyIterator) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for KodyIterator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "KodyIterator")
+    }
+}
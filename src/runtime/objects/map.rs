@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use super::{KodyNumber, KodyObject, KodyValue};
+
+// The values that can be used as map keys
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Bool(bool),
+    Number(KodyNumber),
+    StringLiteral(String),
+}
+
+impl MapKey {
+    // None if the object can't be a key
+    pub fn from_object(object: &KodyObject) -> Option<MapKey> {
+        match object.value.as_ref() {
+            KodyValue::Bool(val) => Some(MapKey::Bool(*val)),
+            KodyValue::Number(val) => Some(MapKey::Number(val.clone())),
+            KodyValue::StringLiteral(val) => Some(MapKey::StringLiteral(val.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_object(&self) -> KodyObject {
+        KodyObject::from(match self {
+            MapKey::Bool(val) => KodyValue::Bool(*val),
+            MapKey::Number(val) => KodyValue::Number(val.clone()),
+            MapKey::StringLiteral(val) => KodyValue::StringLiteral(val.clone()),
+        })
+    }
+}
+
+// A map remembers the order in which its keys were first inserted.
+// Printing it, keys and values all follow that order. Setting the value
+// of an existing key keeps its place, removing a key and inserting it
+// again moves it to the end.
+#[derive(Debug, Clone, Default)]
+pub struct KodyMap {
+    entries: Vec<(MapKey, KodyObject)>,
+    // the position of every key in entries
+    indices: HashMap<MapKey, usize>,
+}
+
+impl KodyMap {
+    pub fn new() -> KodyMap {
+        KodyMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&KodyObject> {
+        self.indices.get(key).map(|index| &self.entries[*index].1)
+    }
+
+    pub fn insert(&mut self, key: MapKey, value: KodyObject) {
+        match self.indices.get(&key) {
+            Some(index) => self.entries[*index].1 = value,
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<KodyObject> {
+        let index = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(index);
+        // everything after the removed entry moved one step back
+        for (key, _) in &self.entries[index..] {
+            if let Some(later_index) = self.indices.get_mut(key) {
+                *later_index -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(MapKey, KodyObject)> {
+        self.entries.iter()
+    }
+}
+
+// two maps are equal if they have the same keys and values in any order
+impl PartialEq for KodyMap {
+    fn eq(&self, other: &KodyMap) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(name: &str) -> MapKey {
+        MapKey::StringLiteral(String::from(name))
+    }
+
+    fn number(x: i64) -> KodyObject {
+        KodyObject::from(KodyValue::Number(KodyNumber::from_int(x)))
+    }
+
+    #[test]
+    fn insertion_order() {
+        let mut map = KodyMap::new();
+        map.insert(key("b"), number(1));
+        map.insert(key("a"), number(2));
+        map.insert(key("c"), number(3));
+        map.insert(key("b"), number(4));
+        assert_eq!(map.remove(&key("a")), Some(number(2)));
+        map.insert(key("a"), number(5));
+
+        let entries = map.iter().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (key("b"), number(4)),
+                (key("c"), number(3)),
+                (key("a"), number(5))
+            ]
+        );
+        assert_eq!(map.get(&key("c")), Some(&number(3)));
+        assert_eq!(map.remove(&key("d")), None);
+    }
+
+    #[test]
+    fn key_types() {
+        let mut map = KodyMap::new();
+        map.insert(MapKey::Bool(true), number(1));
+        map.insert(MapKey::Number(KodyNumber::from_int(1)), number(2));
+        map.insert(key("1"), number(3));
+        assert_eq!(map.len(), 3);
+        assert_eq!(
+            map.get(&MapKey::from_object(&number(1)).unwrap()),
+            Some(&number(2))
+        );
+        assert_eq!(
+            MapKey::from_object(&KodyObject::from(KodyValue::List(Default::default()))),
+            None
+        );
+    }
+}
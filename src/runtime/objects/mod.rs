@@ -1,34 +1,243 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use super::Scope;
+use crate::bytecode::{Chunk, SharedVariable};
+use crate::libkody::Context;
 use crate::syntax_tree::KodyFunctionData;
 
+mod compare;
+mod convert;
+mod iterator;
+mod map;
 mod number;
 
+pub use convert::{
+    check_argument_count, from_arguments, from_each_argument, FromArguments, FromKody, IntoKody,
+    IntoKodyResult, TypedFunction,
+};
+pub use iterator::{Iterate, KodyIterator};
+pub use map::{KodyMap, MapKey};
 pub use number::KodyNumber;
 
+pub type BuiltinFunction = fn(&mut dyn Context, Vec<KodyObject>) -> Result<KodyObject, String>;
+pub type HostFunction = dyn Fn(&mut dyn Context, Vec<KodyObject>) -> Result<KodyObject, String>;
+
+// A function written in Rust, either one of the natives every program has
+// or one that a host program registered
+#[derive(Clone)]
+pub enum NativeFunction {
+    Builtin(BuiltinFunction),
+    Host(Rc<HostFunction>),
+}
+
+impl NativeFunction {
+    pub fn call(
+        &self,
+        context: &mut dyn Context,
+        arguments: Vec<KodyObject>,
+    ) -> Result<KodyObject, String> {
+        match self {
+            NativeFunction::Builtin(function) => function(context, arguments),
+            NativeFunction::Host(function) => function(context, arguments),
+        }
+    }
+}
+
+impl PartialEq for NativeFunction {
+    #[allow(unpredictable_function_pointer_comparisons)]
+    fn eq(&self, other: &NativeFunction) -> bool {
+        match (self, other) {
+            (NativeFunction::Builtin(a), NativeFunction::Builtin(b)) => a == b,
+            (NativeFunction::Host(a), NativeFunction::Host(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NativeFunction::Builtin(_) => write!(f, "Builtin"),
+            NativeFunction::Host(_) => write!(f, "Host"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct KodyObject {
-    pub value: Box<KodyValue>,
+    pub value: Rc<KodyValue>,
 }
 
 impl KodyObject {
     pub fn new() -> KodyObject {
         KodyObject {
-            value: Box::new(KodyValue::Empty),
+            value: Rc::new(KodyValue::Empty),
         }
     }
 
     pub fn from(value: KodyValue) -> KodyObject {
         KodyObject {
-            value: Box::new(value),
+            value: Rc::new(value),
         }
     }
 }
 
+impl Default for KodyObject {
+    fn default() -> KodyObject {
+        KodyObject::new()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum KodyValue {
     Bool(bool),
     Number(KodyNumber),
     StringLiteral(String),
-    Function(KodyFunctionData),
-    NativeFunction(fn(Vec<KodyObject>) -> Result<KodyObject, String>),
+    // lists are shared between every object that refers to them,
+    // so changing one changes all of them
+    List(RefCell<Vec<KodyObject>>),
+    // maps are shared the same way as lists
+    Map(RefCell<KodyMap>),
+    Function(Rc<KodyFunction>),
+    NativeFunction(NativeFunction),
+    // a native function that gets the receiver of obj.method(..)
+    // as its first argument
+    Method {
+        receiver: KodyObject,
+        function: NativeFunction,
+    },
+    // gives the items of a for loop
+    Iterator(Rc<KodyIterator>),
     Empty,
 }
+
+// A function together with the variables of the code around its definition
+#[derive(Debug)]
+pub struct KodyFunction {
+    pub data: Rc<KodyFunctionData>,
+    pub captured: Captured,
+}
+
+// a function is only equal to itself
+impl PartialEq for KodyFunction {
+    fn eq(&self, other: &KodyFunction) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// Each backend keeps the captured variables in its own way, so a function
+// can only be called by the backend that created it.
+pub enum Captured {
+    // the tree walker keeps the whole scope the function was defined in
+    Scope(Rc<RefCell<Scope>>),
+    // the bytecode keeps only the variables the function uses,
+    // in the order its chunk expects them
+    Variables {
+        chunk: Rc<Chunk>,
+        variables: Rc<Vec<SharedVariable>>,
+    },
+}
+
+// the captured variables can contain the function itself,
+// so they are not printed
+impl fmt::Debug for Captured {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Captured::Scope(_) => write!(f, "Scope"),
+            Captured::Variables { variables, .. } => write!(f, "Variables({})", variables.len()),
+        }
+    }
+}
+
+impl KodyValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            KodyValue::Bool(_) => "bool",
+            KodyValue::Number(_) => "number",
+            KodyValue::StringLiteral(_) => "string",
+            KodyValue::List(_) => "list",
+            KodyValue::Map(_) => "map",
+            KodyValue::Function(_) | KodyValue::NativeFunction(_) => "function",
+            KodyValue::Method { .. } => "method",
+            KodyValue::Iterator(_) => "iterator",
+            KodyValue::Empty => "empty",
+        }
+    }
+
+    // the type name with a or an before it, for error messages
+    pub fn type_name_with_article(&self) -> String {
+        let name = self.type_name();
+        let article = if name.starts_with('e') { "an" } else { "a" };
+        format!("{} {}", article, name)
+    }
+
+    // lists that are already being formatted get printed as [...]
+    // so that a list containing itself doesn't recurse forever
+    fn format(&self, f: &mut fmt::Formatter, parents: &mut Vec<*const KodyValue>) -> fmt::Result {
+        match self {
+            KodyValue::Bool(val) => write!(f, "{}", val),
+            KodyValue::Number(val) => write!(f, "{}", val),
+            KodyValue::StringLiteral(val) => write!(f, "{}", val),
+            KodyValue::List(items) => {
+                if parents.contains(&(self as *const KodyValue)) {
+                    return write!(f, "[...]");
+                }
+                parents.push(self);
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.value.format_item(f, parents)?;
+                }
+                parents.pop();
+                write!(f, "]")
+            }
+            KodyValue::Map(map) => {
+                if parents.contains(&(self as *const KodyValue)) {
+                    return write!(f, "{{...}}");
+                }
+                parents.push(self);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.to_object().value.format_item(f, parents)?;
+                    write!(f, ": ")?;
+                    value.value.format_item(f, parents)?;
+                }
+                parents.pop();
+                write!(f, "}}")
+            }
+            KodyValue::Function(function) => write!(f, "<function {}>", function.data.name),
+            KodyValue::NativeFunction(_) => write!(f, "<native function>"),
+            KodyValue::Method { .. } => write!(f, "<method>"),
+            KodyValue::Iterator(_) => write!(f, "<iterator>"),
+            KodyValue::Empty => write!(f, "Empty"),
+        }
+    }
+}
+
+impl KodyValue {
+    // strings inside lists and maps are quoted so that
+    // ["a, b"] and ["a", "b"] look different
+    fn format_item(
+        &self,
+        f: &mut fmt::Formatter,
+        parents: &mut Vec<*const KodyValue>,
+    ) -> fmt::Result {
+        match self {
+            KodyValue::StringLiteral(val) => write!(f, "{:?}", val),
+            value => value.format(f, parents),
+        }
+    }
+}
+
+impl fmt::Display for KodyValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.format(f, &mut vec![])
+    }
+}
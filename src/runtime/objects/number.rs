@@ -1,180 +1,472 @@
 use std::cmp::Ordering;
-use std::{fmt, ops, u64};
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::{fmt, ops};
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+// An exact rational number. Integers never overflow and fractions never lose
+// precision, approximations only happen when asked for with to_f64 or when
+// the result can't be exact, as with sqrt(2). Those results are marked as
+// approximate and so is everything computed from them. Approximate numbers
+// are kept at the precision of a float, so they stay small and fast.
+#[derive(Debug, Clone)]
 pub struct KodyNumber {
-    numerator: u128,
-    denominator: u128,
-    is_negative: bool,
+    value: BigRational,
+    is_approximate: bool,
 }
 
+// how many bits the numerator or denominator of a power can have
+const MAX_POWER_BITS: u64 = 1 << 20;
+
 impl KodyNumber {
     pub fn from_int(x: i64) -> KodyNumber {
+        KodyNumber::exact(BigRational::from_integer(BigInt::from(x)))
+    }
+
+    fn exact(value: BigRational) -> KodyNumber {
         KodyNumber {
-            numerator: x.abs() as u128,
-            denominator: 1,
-            is_negative: x < 0,
+            value,
+            is_approximate: false,
         }
     }
 
-    pub fn from_float(x: f64) -> KodyNumber {
-        if x == 0.0 {
-            return KodyNumber {
-                numerator: 0,
-                denominator: 1,
-                is_negative: false,
-            };
-        }
-        let is_negative = x < 0.0;
-        let mut num = x.abs();
-        let mut denominator = 1_u128;
-        while 2.0 * num < u64::MAX as f64 && 2 * denominator < u64::MAX as u128 {
-            num *= 2.0;
-            denominator *= 2;
+    fn new(value: BigRational, is_approximate: bool) -> KodyNumber {
+        if !is_approximate {
+            return KodyNumber::exact(value);
         }
+        // numbers past the range of floats stay as they are
+        let value = value
+            .to_f64()
+            .and_then(BigRational::from_float)
+            .unwrap_or(value);
         KodyNumber {
-            numerator: num as u128,
-            denominator,
-            is_negative,
+            value,
+            is_approximate,
         }
     }
 
-    fn simplify(&mut self) {
-        let gcd = gcd(self.numerator, self.denominator);
-        self.numerator /= gcd;
-        self.denominator /= gcd;
+    // a number computed from self alone
+    fn with_value(&self, value: BigRational) -> KodyNumber {
+        KodyNumber::new(value, self.is_approximate)
+    }
+
+    // a number computed from self and other
+    fn combine(&self, other: &KodyNumber, value: BigRational) -> KodyNumber {
+        KodyNumber::new(value, self.is_approximate || other.is_approximate)
+    }
 
-        while (self.numerator > u64::MAX as u128 || self.denominator > u64::MAX as u128)
-            && self.denominator > 1
+    // Reads a number written in decimal notation such as 12 or 25.3
+    pub fn parse(text: &str) -> Option<KodyNumber> {
+        let (whole, decimals) = match text.find('.') {
+            Some(index) => (&text[..index], &text[index + 1..]),
+            None => (text, ""),
+        };
+        if whole.is_empty() || !(whole.chars().chain(decimals.chars())).all(|c| c.is_ascii_digit())
         {
-            self.numerator /= 2;
-            self.denominator /= 2;
+            return None;
         }
+
+        let numerator = format!("{}{}", whole, decimals).parse::<BigInt>().ok()?;
+        let denominator = num_traits::pow(BigInt::from(10), decimals.len());
+        Some(KodyNumber::exact(BigRational::new(numerator, denominator)))
+    }
+
+    // Division is the only operation that can fail, so it returns None
+    // instead of panicking when dividing by zero.
+    pub fn checked_div(&self, other: &KodyNumber) -> Option<KodyNumber> {
+        if other.value.is_zero() {
+            return None;
+        }
+        Some(self.combine(other, &self.value / &other.value))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.value.is_integer()
+    }
+
+    pub fn is_approximate(&self) -> bool {
+        self.is_approximate
+    }
+
+    // the same number rounded to a float and marked as approximate
+    pub fn to_approximate(&self) -> KodyNumber {
+        KodyNumber::new(self.value.clone(), true)
+    }
+
+    // The digits of the number if it can be written as a finite decimal,
+    // that is when its denominator has no other prime factors than 2 and 5
+    fn to_decimal(&self) -> Option<String> {
+        let mut denominator = self.value.denom().clone();
+        let (two, five) = (BigInt::from(2), BigInt::from(5));
+        let mut places = [0usize; 2];
+        for (factor, count) in [&two, &five].iter().zip(places.iter_mut()) {
+            while (&denominator % *factor).is_zero() {
+                denominator /= *factor;
+                *count += 1;
+            }
+        }
+        if !denominator.is_one() {
+            return None;
+        }
+        let places = places[0].max(places[1]);
+        let scaled = self.value.numer().abs() * num_traits::pow(BigInt::from(10), places)
+            / self.value.denom();
+        let digits = format!("{:0>width$}", scaled, width = places + 1);
+        let (whole, decimals) = digits.split_at(digits.len() - places);
+        let sign = if self.value.is_negative() { "-" } else { "" };
+        Some(format!("{}{}.{}", sign, whole, decimals))
+    }
+
+    pub fn abs(&self) -> KodyNumber {
+        self.with_value(self.value.abs())
+    }
+
+    pub fn floor(&self) -> KodyNumber {
+        self.with_value(self.value.floor())
+    }
+
+    pub fn ceil(&self) -> KodyNumber {
+        self.with_value(self.value.ceil())
+    }
+
+    // halfway between two integers rounds away from zero
+    pub fn round(&self) -> KodyNumber {
+        self.with_value(self.value.round())
+    }
+
+    // the fraction in lowest terms, the denominator is always positive
+    pub fn numerator(&self) -> KodyNumber {
+        self.with_value(BigRational::from_integer(self.value.numer().clone()))
     }
+
+    pub fn denominator(&self) -> KodyNumber {
+        self.with_value(BigRational::from_integer(self.value.denom().clone()))
+    }
+
+    // The greatest common divisor is never negative and gcd(0, 0) is 0.
+    // None if either number is a fraction.
+    pub fn gcd(&self, other: &KodyNumber) -> Option<KodyNumber> {
+        if !self.is_integer() || !other.is_integer() {
+            return None;
+        }
+        let (mut a, mut b) = (self.value.numer().abs(), other.value.numer().abs());
+        while !b.is_zero() {
+            let remainder = &a % &b;
+            a = b;
+            b = remainder;
+        }
+        Some(self.combine(other, BigRational::from_integer(a)))
+    }
+
+    // The least common multiple is never negative and 0 if either number
+    // is 0. None if either number is a fraction.
+    pub fn lcm(&self, other: &KodyNumber) -> Option<KodyNumber> {
+        let gcd = self.gcd(other)?;
+        if gcd.value.is_zero() {
+            return Some(gcd);
+        }
+        let product = (&self.value * &other.value).abs();
+        Some(self.combine(other, product / gcd.value))
+    }
+
+    // None when the exponent is a fraction, zero is raised
+    // to a negative power or the result would be too large
+    pub fn checked_pow(&self, exponent: &KodyNumber) -> Option<KodyNumber> {
+        let power = exponent.to_i128()?;
+        if self.value.is_zero() && power < 0 {
+            return None;
+        }
+        // each multiplication adds at most the bits of the base
+        let bits = self.value.numer().bits().max(self.value.denom().bits()) - 1;
+        let times = u64::try_from(power.unsigned_abs()).ok()?;
+        if bits.checked_mul(times)? > MAX_POWER_BITS {
+            return None;
+        }
+        let raise = |x: &BigInt| num_traits::pow(x.clone(), times as usize);
+        let (numer, denom) = (raise(self.value.numer()), raise(self.value.denom()));
+        let value = if power < 0 {
+            BigRational::new(denom, numer)
+        } else {
+            BigRational::new(numer, denom)
+        };
+        Some(self.combine(exponent, value))
+    }
+
+    // None if the number is negative, a fraction or too large
+    pub fn to_usize(&self) -> Option<usize> {
+        if self.value.is_integer() {
+            self.value.numer().to_usize()
+        } else {
+            None
+        }
+    }
+
+    // None if the number is a fraction or doesn't fit
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.value.is_integer() {
+            self.value.numer().to_i128()
+        } else {
+            None
+        }
+    }
+
+    // None if the number is negative, a fraction or doesn't fit
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.value.is_integer() {
+            self.value.numer().to_u128()
+        } else {
+            None
+        }
+    }
+
+    // Every finite float is a fraction with a power of two as its
+    // denominator, so the conversion is exact. None for NaN and infinities.
+    pub fn from_f64(x: f64) -> Option<KodyNumber> {
+        BigRational::from_float(x).map(KodyNumber::exact)
+    }
+
+    // The same number as from_f64 gives, but marked as approximate
+    // for results that were rounded to the float
+    pub fn approximate(x: f64) -> Option<KodyNumber> {
+        KodyNumber::from_f64(x).map(|number| KodyNumber {
+            is_approximate: true,
+            ..number
+        })
+    }
+
+    // The closest float to the number, this is the only place
+    // where precision is lost.
+    pub fn to_f64(&self) -> f64 {
+        self.value.to_f64().unwrap_or(f64::NAN)
+    }
+}
+
+macro_rules! from_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl From<$integer> for KodyNumber {
+                fn from(x: $integer) -> KodyNumber {
+                    KodyNumber::exact(BigRational::from_integer(BigInt::from(x)))
+                }
+            }
+        )*
+    };
 }
 
-#[allow(clippy::suspicious_arithmetic_impl)]
+from_integer!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
 impl ops::Add for &KodyNumber {
     type Output = KodyNumber;
     fn add(self, other: &KodyNumber) -> KodyNumber {
-        let lcm = lcm(self.denominator, other.denominator);
-        let lhs = self.numerator * (lcm / self.denominator);
-        let rhs = other.numerator * (lcm / other.denominator);
-
-        let (numerator, is_negative) = match (self.is_negative, other.is_negative) {
-            (false, false) => (lhs + rhs, false),
-            (true, false) => (u128::max(lhs, rhs) - u128::min(lhs, rhs), lhs > rhs),
-            (false, true) => (u128::max(lhs, rhs) - u128::min(lhs, rhs), rhs > lhs),
-            (true, true) => (lhs + rhs, true),
-        };
-
-        let mut result = KodyNumber {
-            numerator,
-            denominator: lcm,
-            is_negative,
-        };
-        result.simplify();
-
-        result
+        self.combine(other, &self.value + &other.value)
     }
 }
 
 impl ops::Sub for &KodyNumber {
     type Output = KodyNumber;
     fn sub(self, other: &KodyNumber) -> KodyNumber {
-        self + &(-other)
+        self.combine(other, &self.value - &other.value)
     }
 }
 
-#[allow(clippy::suspicious_arithmetic_impl)]
 impl ops::Mul for &KodyNumber {
     type Output = KodyNumber;
     fn mul(self, other: &KodyNumber) -> KodyNumber {
-        let mut result = KodyNumber {
-            numerator: self.numerator * other.numerator,
-            denominator: self.denominator * other.denominator,
-            is_negative: self.is_negative ^ other.is_negative,
-        };
-        result.simplify();
-
-        result
+        self.combine(other, &self.value * &other.value)
     }
 }
 
-#[allow(clippy::suspicious_arithmetic_impl)]
-impl ops::Div for &KodyNumber {
+impl ops::Neg for &KodyNumber {
     type Output = KodyNumber;
-    fn div(self, other: &KodyNumber) -> KodyNumber {
-        let mut result = KodyNumber {
-            numerator: self.numerator * other.denominator,
-            denominator: self.denominator * other.numerator,
-            is_negative: self.is_negative ^ other.is_negative,
-        };
-        result.simplify();
+    fn neg(self) -> KodyNumber {
+        self.with_value(-&self.value)
+    }
+}
 
-        result
+// Numbers are compared by value alone,
+// so an approximate 2 is equal to an exact 2
+impl PartialEq for KodyNumber {
+    fn eq(&self, other: &KodyNumber) -> bool {
+        self.value == other.value
     }
 }
 
-impl ops::Neg for &KodyNumber {
-    type Output = KodyNumber;
-    fn neg(self) -> KodyNumber {
-        KodyNumber {
-            denominator: self.denominator,
-            numerator: self.numerator,
-            is_negative: !self.is_negative,
-        }
+impl Eq for KodyNumber {}
+
+impl PartialOrd for KodyNumber {
+    fn partial_cmp(&self, other: &KodyNumber) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for KodyNumber {
+    fn cmp(&self, other: &KodyNumber) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl Hash for KodyNumber {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
     }
 }
 
+// Exact numbers are written exactly, as a decimal when it ends and as a
+// fraction like 1/3 otherwise. Approximate numbers are written like floats.
 impl fmt::Display for KodyNumber {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let sign = if self.is_negative { "-" } else { "" };
-        if self.denominator == 1 {
-            write!(f, "{}{}", sign, self.numerator)
-        } else {
-            write!(
-                f,
-                "{}{}",
-                sign,
-                self.numerator as f64 / self.denominator as f64
-            )
+        if self.value.is_integer() {
+            return write!(f, "{}", self.value.numer());
+        }
+        let float = self.to_f64();
+        if self.is_approximate && float.is_finite() {
+            return write!(f, "{}", float);
+        }
+        match self.to_decimal() {
+            Some(decimal) => write!(f, "{}", decimal),
+            None => write!(f, "{}/{}", self.value.numer(), self.value.denom()),
         }
     }
 }
 
-impl Ord for KodyNumber {
-    fn cmp(&self, other: &KodyNumber) -> Ordering {
-        let lcm = lcm(self.denominator, other.denominator);
-        let lhs = self.numerator * (lcm / self.denominator);
-        let rhs = other.numerator * (lcm / other.denominator);
+#[cfg(test)]
+mod test {
+    use super::KodyNumber;
+
+    fn number(x: i64) -> KodyNumber {
+        KodyNumber::from_int(x)
+    }
+
+    fn fraction(numerator: i64, denominator: i64) -> KodyNumber {
+        number(numerator).checked_div(&number(denominator)).unwrap()
+    }
 
-        match (self.is_negative, other.is_negative) {
-            (false, false) => lhs.cmp(&rhs),
-            (true, false) => Ordering::Less,
-            (false, true) => Ordering::Greater,
-            (true, true) => rhs.cmp(&lhs),
+    #[test]
+    fn no_overflow() {
+        let mut factorial = number(1);
+        for i in 1..=40 {
+            factorial = &factorial * &number(i);
         }
+        assert_eq!(
+            factorial.to_string(),
+            "815915283247897734345611269596115894272000000000"
+        );
     }
-}
 
-impl PartialOrd for KodyNumber {
-    fn partial_cmp(&self, other: &KodyNumber) -> Option<Ordering> {
-        Some(self.cmp(other))
+    #[test]
+    fn exact_fractions() {
+        let third = fraction(1, 3);
+        assert_eq!(&(&third + &third) + &third, number(1));
+        assert!(!third.is_integer());
+
+        let tenth = KodyNumber::parse("0.1").unwrap();
+        assert_eq!(
+            &(&tenth + &tenth) + &tenth,
+            KodyNumber::parse("0.3").unwrap()
+        );
+
+        // a tiny fraction stays exact however small it gets
+        let mut tiny = number(1);
+        for _ in 0..200 {
+            tiny = tiny.checked_div(&number(10)).unwrap();
+        }
+        for _ in 0..200 {
+            tiny = &tiny * &number(10);
+        }
+        assert_eq!(tiny, number(1));
     }
-}
 
-fn gcd(x: u128, y: u128) -> u128 {
-    let mut dividend = u128::max(x, y);
-    let mut divisor = u128::min(x, y);
-    while divisor > 0 {
-        let remainder = dividend % divisor;
-        dividend = divisor;
-        divisor = remainder;
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(number(1).checked_div(&number(0)), None);
+        assert_eq!(fraction(0, 5).checked_div(&fraction(0, 3)), None);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(KodyNumber::parse("12"), Some(number(12)));
+        assert_eq!(KodyNumber::parse("25.3"), Some(fraction(253, 10)));
+        assert_eq!(
+            KodyNumber::parse("123456789012345678901234567890").map(|x| x.to_string()),
+            Some(String::from("123456789012345678901234567890"))
+        );
+        assert_eq!(KodyNumber::parse(""), None);
+        assert_eq!(KodyNumber::parse("1.2.3"), None);
+    }
+
+    #[test]
+    fn rounding() {
+        let half = fraction(-5, 2);
+        assert_eq!(half.abs(), fraction(5, 2));
+        assert_eq!(half.floor(), number(-3));
+        assert_eq!(half.ceil(), number(-2));
+        assert_eq!(half.round(), number(-3));
+        assert_eq!(fraction(6, -4).numerator(), number(-3));
+        assert_eq!(fraction(6, -4).denominator(), number(2));
+    }
+
+    #[test]
+    fn divisors() {
+        assert_eq!(number(-12).gcd(&number(18)), Some(number(6)));
+        assert_eq!(number(0).gcd(&number(0)), Some(number(0)));
+        assert_eq!(number(4).lcm(&number(-6)), Some(number(12)));
+        assert_eq!(number(0).lcm(&number(5)), Some(number(0)));
+        assert_eq!(fraction(1, 2).gcd(&number(2)), None);
+    }
+
+    #[test]
+    fn powers() {
+        let pow = |base: KodyNumber, exponent: i64| base.checked_pow(&number(exponent));
+        assert_eq!(pow(fraction(2, 3), 3), Some(fraction(8, 27)));
+        assert_eq!(pow(fraction(-2, 3), -3), Some(fraction(-27, 8)));
+        assert_eq!(pow(number(5), 0), Some(number(1)));
+        assert_eq!(pow(number(0), -1), None);
+        assert_eq!(pow(number(1), i64::MAX), Some(number(1)));
+        assert_eq!(pow(number(2), i64::MAX), None);
+        assert_eq!(number(4).checked_pow(&fraction(1, 2)), None);
+    }
+
+    #[test]
+    fn approximations() {
+        let root = KodyNumber::approximate(2f64.sqrt()).unwrap();
+        assert!(root.is_approximate());
+        assert!((&root * &number(0)).is_approximate());
+        assert!(!(&number(1) + &number(1)).is_approximate());
+        assert_eq!(KodyNumber::approximate(2.0), Some(number(2)));
+        assert_eq!(KodyNumber::approximate(f64::INFINITY), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(fraction(-5, 2).to_string(), "-2.5");
+        assert_eq!(fraction(1, 80).to_string(), "0.0125");
+        assert_eq!(fraction(1, 3).to_string(), "1/3");
+        assert_eq!(fraction(-4, 6).to_string(), "-2/3");
+        let big = number(10).checked_pow(&number(400)).unwrap();
+        assert_eq!(
+            number(1).checked_div(&big).unwrap().to_string(),
+            format!("0.{}1", "0".repeat(399))
+        );
+        assert_eq!(
+            big.checked_div(&number(3)).unwrap().to_string(),
+            format!("1{}/3", "0".repeat(400))
+        );
+        assert_eq!(
+            fraction(1, 3).to_approximate().to_string(),
+            "0.3333333333333333"
+        );
+        // approximate numbers stay floats
+        let third = fraction(1, 3).to_approximate();
+        assert_eq!((&third + &third).to_string(), "0.6666666666666666");
     }
-    dividend
-}
 
-fn lcm(x: u128, y: u128) -> u128 {
-    (x * y) / gcd(x, y)
+    #[test]
+    fn ordering() {
+        assert!(number(-5) < number(3));
+        assert!(fraction(1, 3) < fraction(1, 2));
+        assert!(fraction(-1, 3) > fraction(-1, 2));
+    }
 }
@@ -0,0 +1,31 @@
+use std::fmt;
+
+// The location of a piece of source code.
+// start and end are byte offsets, line and column point to the first character
+// and start from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    // Returns a span that covers everything from the start of self
+    // to the end of other.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: usize::max(self.end, other.end),
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
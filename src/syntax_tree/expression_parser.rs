@@ -1,462 +1,886 @@
-use super::{get_next_expression, identify_expressions, KodyNode};
+// A single pass precedence climbing (Pratt) parser.
+//
+// Operator precedence from the loosest to the tightest binding:
+//
+// | level | operators                           | associativity |
+// |-------|-------------------------------------|---------------|
+// | 1     | = += -= *= /=                       | right         |
+// | 2     | or                                  | left          |
+// | 3     | and                                 | left          |
+// | 4     | not (prefix)                        |               |
+// | 5     | == != < > <= >=                     | left          |
+// | 6     | + -                                 | left          |
+// | 7     | * /                                 | left          |
+// | 8     | - (prefix)                          |               |
+// | 9     | call f(..), index a[..], member a.b | left          |
+//
+// if, while, for and return are prefix forms that take whole expressions
+// as their operands, so they bind looser than anything above. An if
+// chain is written as
+//
+//   if condition action (else if | elif condition action)* (else action)?
+
+use std::mem;
+use std::rc::Rc;
+
+use super::{IfBranch, KodyFunctionData, KodyNode, Operation};
+use crate::error::KodyError;
 use crate::runtime::objects::{KodyNumber, KodyObject, KodyValue};
-use crate::tokenizer::Token;
-
-fn is_in_codeblock(tokens: &[Token], index: usize) -> bool {
-    tokens
-        .split_at(index)
-        .0
-        .iter()
-        .filter(|t| *t == &Token::OpenCurlyBrackets)
-        .count()
-        > tokens
-            .split_at(index)
-            .0
-            .iter()
-            .filter(|t| *t == &Token::CloseCurlyBrackets)
-            .count()
+use crate::span::Span;
+use crate::tokenizer::{FormatPart, SpannedToken, Token};
+
+const LOWEST: u8 = 0;
+const ASSIGNMENT: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const NOT: u8 = 4;
+const COMPARISON: u8 = 5;
+const SUM: u8 = 6;
+const PRODUCT: u8 = 7;
+const NEGATION: u8 = 8;
+const CALL: u8 = 9;
+
+// how deeply expressions can nest, deeper programs would overflow the
+// stack when they are parsed, compiled or run
+const MAX_DEPTH: usize = 64;
+// how tall the syntax tree can grow, a.b.c or f()() are parsed in a loop
+// but still put the first member or call below the second one, operator
+// chains like a + b + c are flat and can be as long as they like
+const MAX_HEIGHT: usize = 256;
+
+fn infix_precedence(token: &Token) -> Option<u8> {
+    Some(match token {
+        Token::Assign
+        | Token::AddAssign
+        | Token::SubtractAssign
+        | Token::MultiplyAssign
+        | Token::DivideAssign => ASSIGNMENT,
+        Token::Or => OR,
+        Token::And => AND,
+        Token::Equals
+        | Token::NotEqual
+        | Token::GreaterThan
+        | Token::GreaterThanOrEqual
+        | Token::LessThan
+        | Token::LessThanOrEqual => COMPARISON,
+        Token::Add | Token::Subtract => SUM,
+        Token::Multiply | Token::Divide => PRODUCT,
+        Token::OpenParentheses | Token::OpenSquareBrackets | Token::MemberAccess => CALL,
+        _ => return None,
+    })
 }
 
-fn is_in_parentheses(tokens: &[Token], index: usize) -> bool {
-    tokens
-        .split_at(index)
-        .0
-        .iter()
-        .filter(|t| *t == &Token::OpenParentheses)
-        .count()
-        > tokens
-            .split_at(index)
-            .0
-            .iter()
-            .filter(|t| *t == &Token::CloseParentheses)
-            .count()
+fn binary_function_name(token: &Token) -> &'static str {
+    match token {
+        Token::Or => "__or",
+        Token::And => "__and",
+        Token::Equals => "__equal",
+        Token::NotEqual => "__not_equal",
+        Token::GreaterThan => "__greater_than",
+        Token::GreaterThanOrEqual => "__greater_than_or_equal",
+        Token::LessThan => "__less_than",
+        Token::LessThanOrEqual => "__less_than_or_equal",
+        Token::Add | Token::AddAssign => "__add",
+        Token::Subtract | Token::SubtractAssign => "__subtract",
+        Token::Multiply | Token::MultiplyAssign => "__multiply",
+        Token::Divide | Token::DivideAssign => "__divide",
+        _ => unreachable!(),
+    }
 }
 
-fn check_parentheses(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if tokens.first() == Some(&Token::OpenParentheses)
-        && tokens.last() == Some(&Token::CloseParentheses)
-    {
-        return parse_expression_tokens(&tokens[1..tokens.len() - 1]).map(Some);
+fn call_native(name: &str, arguments: Vec<KodyNode>, span: Span) -> KodyNode {
+    KodyNode::CallFunction {
+        function: Box::new(KodyNode::GetVariable {
+            name: name.to_string(),
+            span,
+        }),
+        arguments,
+        span,
     }
-    Ok(None)
 }
 
-fn check_codeblock(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if tokens.first() == Some(&Token::OpenCurlyBrackets)
-        && tokens.last() == Some(&Token::CloseCurlyBrackets)
-    {
-        let expressions = identify_expressions(&tokens[1..tokens.len() - 1])?;
-        let mut statements = vec![];
-        for expression in expressions {
-            statements.push(parse_expression_tokens(expression)?);
+// Makes the last statement of a lambda body its return value
+// if it is an expression that has a value
+fn return_last_value(body: KodyNode) -> KodyNode {
+    let has_value = |node: &KodyNode| {
+        matches!(
+            node,
+            KodyNode::GetConstant { .. }
+                | KodyNode::GetVariable { .. }
+                | KodyNode::CallFunction { .. }
+                | KodyNode::OperatorChain { .. }
+                | KodyNode::GetMember { .. }
+                | KodyNode::GetIndex { .. }
+                | KodyNode::Lambda { .. }
+        )
+    };
+    match body {
+        KodyNode::CodeBlock {
+            mut statements,
+            span,
+        } => {
+            if let Some(last) = statements.pop() {
+                statements.push(if has_value(&last) {
+                    KodyNode::ReturnFromFunction {
+                        span: last.span(),
+                        return_value: Box::new(last),
+                    }
+                } else {
+                    last
+                });
+            }
+            KodyNode::CodeBlock { statements, span }
         }
-        return Ok(Some(KodyNode::CodeBlock { statements }));
+        node if has_value(&node) => KodyNode::ReturnFromFunction {
+            span: node.span(),
+            return_value: Box::new(node),
+        },
+        node => node,
     }
-    Ok(None)
 }
 
-fn check_return(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if tokens.first() == Some(&Token::Return) {
-        let return_value = if tokens.len() == 1 {
-            Box::new(KodyNode::GetConstant {
-                value: KodyObject::from(KodyValue::Empty),
-            })
-        } else {
-            Box::new(parse_expression_tokens(&tokens[1..tokens.len()])?)
-        };
-        return Ok(Some(KodyNode::ReturnFromFunction { return_value }));
-    }
-    Ok(None)
+pub struct Parser<'a> {
+    tokens: &'a [SpannedToken],
+    position: usize,
+    // how many loops the code being parsed is in, break and continue
+    // need at least one. A function body starts again at zero.
+    loop_depth: usize,
+    // how deeply the expression being parsed is nested
+    depth: usize,
+    // the height of the syntax tree of the expressions parsed last
+    height: usize,
 }
 
-fn check_if_expression(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if tokens.first() == Some(&Token::If) {
-        let (condition_tokens, other_tokens) = get_next_expression(&tokens[1..tokens.len()])?;
-        let (action_tokens, other_tokens) = get_next_expression(&other_tokens)?;
-        let else_action = if other_tokens.first() == Some(&Token::Else) {
-            Some(Box::new(parse_expression_tokens(
-                &other_tokens[1..other_tokens.len()],
-            )?))
-        } else {
-            None
-        };
-        let condition = parse_expression_tokens(condition_tokens)?;
-        let action = parse_expression_tokens(action_tokens)?;
-        return Ok(Some(KodyNode::IfStatement {
-            condition: Box::new(condition),
-            action: Box::new(action),
-            else_action,
-        }));
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [SpannedToken]) -> Parser<'a> {
+        Parser {
+            tokens,
+            position: 0,
+            loop_depth: 0,
+            depth: 0,
+            height: 0,
+        }
     }
-    Ok(None)
-}
 
-fn check_while_expression(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if tokens.first() == Some(&Token::While) {
-        let (condition_tokens, other_tokens) = get_next_expression(&tokens[1..tokens.len()])?;
-        let (action_tokens, _) = get_next_expression(&other_tokens)?;
-        let condition = parse_expression_tokens(condition_tokens)?;
-        let action = parse_expression_tokens(action_tokens)?;
-        return Ok(Some(KodyNode::WhileStatement {
-            condition: Box::new(condition),
-            action: Box::new(action),
-        }));
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position).map(|t| &t.token)
     }
-    Ok(None)
-}
 
-fn check_negation(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if tokens.first() == Some(&Token::Subtract) {
-        return Ok(Some(KodyNode::CallFunction {
-            function: Box::new(KodyNode::GetVariable {
-                name: String::from("__negate"),
+    // the token after the next one
+    fn peek_second(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position + 1).map(|t| &t.token)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    // the span of the next token or the end of the input if there are no tokens left
+    fn here(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some(token) => token.span,
+            None => self.tokens.last().map_or_else(Span::default, |token| Span {
+                start: token.span.end,
+                column: token.span.column + (token.span.end - token.span.start),
+                ..token.span
             }),
-            arguments: vec![parse_expression_tokens(&tokens[1..])?],
-        }));
+        }
     }
-    Ok(None)
-}
 
-fn check_value(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if tokens.len() == 1 {
-        return Ok(Some(match tokens.first().unwrap() {
-            Token::Identifier(name) => KodyNode::GetVariable { name: name.clone() },
-            Token::StringLiteral(value) => KodyNode::GetConstant {
-                value: KodyObject::from(KodyValue::StringLiteral(value.clone())),
-            },
-            Token::Number(val) => KodyNode::GetConstant {
-                value: KodyObject::from(KodyValue::Number(if val.contains('.') {
-                    KodyNumber::from_float(val.parse().unwrap())
-                } else {
-                    KodyNumber::from_int(val.parse().unwrap())
-                })),
-            },
-            Token::True => KodyNode::GetConstant {
-                value: KodyObject::from(KodyValue::Bool(true)),
-            },
-            Token::False => KodyNode::GetConstant {
-                value: KodyObject::from(KodyValue::Bool(false)),
-            },
-            _ => unreachable!(),
-        }));
+    // the span from start to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        match self
+            .position
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+        {
+            Some(token) => start.to(token.span),
+            None => start,
+        }
     }
-    Ok(None)
-}
 
-fn check_assignment(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if let Some(i) = tokens.iter().enumerate().position(|(index, t)| match t {
-        Token::Assign
-        | Token::AddAssign
-        | Token::SubtractAssign
-        | Token::MultiplyAssign
-        | Token::DivideAssign => !is_in_codeblock(tokens, index),
-        _ => false,
-    }) {
-        let (variable_tokens, mut value_tokens) = tokens.split_at(i);
-        // remove the assignment operator
-        value_tokens = match value_tokens.split_first() {
-            Some((_first, rest)) => rest,
-            None => return Err(String::from("No value after assign operator!")),
-        };
+    fn error(&self, code: &'static str, message: &str) -> KodyError {
+        KodyError::parse(code, message, self.here())
+    }
 
-        let variable_name = if let [Token::Identifier(identifier)] = variable_tokens {
-            identifier.clone()
+    fn expect(
+        &mut self,
+        expected: &Token,
+        code: &'static str,
+        error: &str,
+    ) -> Result<(), KodyError> {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
         } else {
-            return Err(String::from("Cannot assign to a non-identifier variable!"));
-        };
-
-        if tokens[i] == Token::Assign {
-            return Ok(Some(KodyNode::SetVariable {
-                name: variable_name,
-                value: Box::new(parse_expression_tokens(value_tokens)?),
-            }));
+            Err(self.error(code, error))
         }
+    }
 
-        let function_name = match tokens[i] {
-            Token::AddAssign => "__add",
-            Token::SubtractAssign => "__subtract",
-            Token::MultiplyAssign => "__multiply",
-            Token::DivideAssign => "__divide",
-            _ => unreachable!(),
-        };
+    // Parses statements until the closing token or the end of the input.
+    // The closing token itself is not consumed.
+    pub fn parse_statements(
+        &mut self,
+        closing: Option<&Token>,
+    ) -> Result<Vec<KodyNode>, KodyError> {
+        let mut statements = vec![];
+        while let Some(token) = self.peek() {
+            if Some(token) == closing {
+                break;
+            }
+            // func( starts a lambda instead of a definition
+            if token == &Token::FunctionDef && self.peek_second() != Some(&Token::OpenParentheses) {
+                statements.push(self.parse_function_definition()?);
+            } else {
+                statements.push(self.parse_action()?);
+            }
+        }
+        Ok(statements)
+    }
 
-        return Ok(Some(KodyNode::SetVariable {
-            name: variable_name,
-            value: Box::new(KodyNode::CallFunction {
-                function: Box::new(KodyNode::GetVariable {
-                    name: function_name.to_string(),
-                }),
-                arguments: vec![
-                    parse_expression_tokens(variable_tokens)?,
-                    parse_expression_tokens(value_tokens)?,
-                ],
-            }),
-        }));
+    // A statement or the action of an if, a loop or a function.
+    // {} is an empty code block there instead of an empty map.
+    fn parse_action(&mut self) -> Result<KodyNode, KodyError> {
+        if self.peek() == Some(&Token::OpenCurlyBrackets)
+            && self.peek_second() == Some(&Token::CloseCurlyBrackets)
+        {
+            let start = self.here();
+            self.position += 2;
+            return Ok(KodyNode::CodeBlock {
+                statements: vec![],
+                span: self.span_from(start),
+            });
+        }
+        self.parse_expression(LOWEST)
     }
-    Ok(None)
-}
 
-fn check_comparison(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if let Some(i) = tokens.iter().enumerate().position(|(index, t)| match t {
-        Token::Equals
-        | Token::NotEqual
-        | Token::GreaterThanOrEqual
-        | Token::GreaterThan
-        | Token::LessThanOrEqual
-        | Token::LessThan => !is_in_codeblock(tokens, index) && !is_in_parentheses(tokens, index),
-        _ => false,
-    }) {
-        let function_name = match tokens[i] {
-            Token::Equals => "__equal",
-            Token::NotEqual => "__not_equal",
-            Token::GreaterThan => "__greater_than",
-            Token::GreaterThanOrEqual => "__greater_than_or_equal",
-            Token::LessThan => "__less_than",
-            Token::LessThanOrEqual => "__less_than_or_equal",
-            _ => unreachable!(),
+    fn parse_function_definition(&mut self) -> Result<KodyNode, KodyError> {
+        let start = self.here();
+        // skip the func keyword
+        self.next();
+
+        let name = match self.peek() {
+            Some(Token::Identifier(function_name)) => function_name.clone(),
+            _ => return Err(self.error("E0105", "Expected identifier after function keyword!")),
         };
-        return Ok(Some(KodyNode::CallFunction {
-            function: Box::new(KodyNode::GetVariable {
-                name: function_name.to_string(),
+        self.next();
+
+        let arguments = self.parse_arguments()?;
+        let body = self.parse_function_body()?;
+
+        Ok(KodyNode::FunctionDefinition {
+            function: Rc::new(KodyFunctionData {
+                name,
+                arguments,
+                body,
             }),
-            arguments: vec![
-                parse_expression_tokens(tokens.split_at(i).0)?,
-                parse_expression_tokens(tokens.split_at(i + 1).1)?,
-            ],
-        }));
+            span: self.span_from(start),
+        })
     }
-    Ok(None)
-}
 
-fn check_addition_and_subtraction(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if let Some(i) = tokens.iter().enumerate().position(|(index, t)| match t {
-        Token::Add | Token::Subtract => {
-            !is_in_codeblock(tokens, index)
-                && !is_in_parentheses(tokens, index)
-                && index != 0
-                && match tokens[index - 1] {
-                    Token::Number(_)
-                    | Token::Identifier(_)
-                    | Token::StringLiteral(_)
-                    | Token::CloseParentheses
-                    | Token::CloseCurlyBrackets => true,
-                    _ => false,
+    fn parse_arguments(&mut self) -> Result<Vec<String>, KodyError> {
+        self.expect(
+            &Token::OpenParentheses,
+            "E0105",
+            "Expected parentheses after function identifier!",
+        )?;
+
+        let mut arguments = vec![];
+        if self.peek() == Some(&Token::CloseParentheses) {
+            self.next();
+        } else {
+            loop {
+                match self.peek() {
+                    Some(Token::Identifier(name)) => arguments.push(name.clone()),
+                    _ => return Err(self.error("E0105", "Unexpexted token in function arguments!")),
                 }
+                self.next();
+                match self.peek() {
+                    Some(Token::Separator) => (),
+                    Some(Token::CloseParentheses) => {
+                        self.next();
+                        break;
+                    }
+                    _ => return Err(self.error("E0105", "Unexpexted token in function arguments!")),
+                }
+                self.next();
+            }
         }
-        _ => false,
-    }) {
-        if tokens.get(i + 1) == Some(&Token::Add) || tokens.get(i + 1) == Some(&Token::Subtract) {
-            return Err(String::from(
-                "Two consecutive addition or subtraction symbols",
-            ));
-        }
-        let function_name = match tokens[i] {
-            Token::Add => "__add",
-            Token::Subtract => "__subtract",
-            _ => unreachable!(),
-        };
-        return Ok(Some(KodyNode::CallFunction {
-            function: Box::new(KodyNode::GetVariable {
-                name: function_name.to_string(),
-            }),
-            arguments: vec![
-                parse_expression_tokens(tokens.split_at(i).0)?,
-                parse_expression_tokens(tokens.split_at(i + 1).1)?,
-            ],
-        }));
+        Ok(arguments)
     }
-    Ok(None)
-}
 
-fn check_multiplication_and_division(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if let Some(i) = tokens.iter().enumerate().position(|(index, t)| match t {
-        Token::Multiply | Token::Divide => {
-            !is_in_codeblock(tokens, index) && !is_in_parentheses(tokens, index)
+    fn parse_function_body(&mut self) -> Result<KodyNode, KodyError> {
+        if self.peek().is_none() {
+            return Err(self.error("E0105", "Expected a function body!"));
         }
-        _ => false,
-    }) {
-        let function_name = match tokens[i] {
-            Token::Multiply => "__multiply",
-            Token::Divide => "__divide",
-            _ => unreachable!(),
+        let outer_loop_depth = mem::replace(&mut self.loop_depth, 0);
+        // a block body ends at its }, so func(x) { .. }(1) calls the function
+        let body = if self.peek() != Some(&Token::OpenCurlyBrackets) {
+            self.parse_expression(LOWEST)
+        } else if self.peek_second() == Some(&Token::CloseCurlyBrackets) {
+            self.parse_action()
+        } else {
+            self.parse_prefix()
         };
-        return Ok(Some(KodyNode::CallFunction {
-            function: Box::new(KodyNode::GetVariable {
-                name: function_name.to_string(),
-            }),
-            arguments: vec![
-                parse_expression_tokens(tokens.split_at(i).0)?,
-                parse_expression_tokens(tokens.split_at(i + 1).1)?,
-            ],
-        }));
+        self.loop_depth = outer_loop_depth;
+        body
     }
-    Ok(None)
-}
 
-fn check_or_operator(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if let Some(i) = tokens.iter().enumerate().position(|(index, t)| match t {
-        Token::Or => !is_in_codeblock(tokens, index) && !is_in_parentheses(tokens, index),
-        _ => false,
-    }) {
-        return Ok(Some(KodyNode::CallFunction {
-            function: Box::new(KodyNode::GetVariable {
-                name: String::from("__or"),
+    // func(arguments) body, a function without a name that is an expression.
+    // Its body returns the value of its last expression, so func(x) { x * 2 }
+    // doubles its argument.
+    fn parse_lambda(&mut self, start: Span) -> Result<KodyNode, KodyError> {
+        let arguments = self.parse_arguments()?;
+        let body = return_last_value(self.parse_function_body()?);
+
+        Ok(KodyNode::Lambda {
+            function: Rc::new(KodyFunctionData {
+                name: String::from("lambda"),
+                arguments,
+                body,
             }),
-            arguments: vec![
-                parse_expression_tokens(tokens.split_at(i).0)?,
-                parse_expression_tokens(tokens.split_at(i + 1).1)?,
-            ],
-        }));
+            span: self.span_from(start),
+        })
     }
-    Ok(None)
-}
 
-fn check_and_operator(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if let Some(i) = tokens.iter().enumerate().position(|(index, t)| match t {
-        Token::And => !is_in_codeblock(tokens, index) && !is_in_parentheses(tokens, index),
-        _ => false,
-    }) {
-        return Ok(Some(KodyNode::CallFunction {
-            function: Box::new(KodyNode::GetVariable {
-                name: String::from("__and"),
-            }),
-            arguments: vec![
-                parse_expression_tokens(tokens.split_at(i).0)?,
-                parse_expression_tokens(tokens.split_at(i + 1).1)?,
-            ],
-        }));
+    pub fn parse_expression(&mut self, precedence: u8) -> Result<KodyNode, KodyError> {
+        let outer_depth = self.depth;
+        self.nest()?;
+        let outer_height = mem::replace(&mut self.height, 0);
+        let mut left = self.parse_prefix()?;
+        // the expressions inside the prefix are below it
+        let mut height = self.height + 1;
+
+        while let Some(token) = self.peek() {
+            let token_precedence = match infix_precedence(token) {
+                Some(token_precedence) if token_precedence > precedence => token_precedence,
+                // assignment is right associative
+                Some(ASSIGNMENT) if precedence == ASSIGNMENT => ASSIGNMENT,
+                _ => break,
+            };
+            // an operation that goes into the chain on the left
+            // doesn't make the tree taller
+            let extends_chain = matches!(left, KodyNode::OperatorChain { .. })
+                && token_precedence != CALL
+                && token_precedence != ASSIGNMENT;
+            self.height = 0;
+            left = self.parse_infix(left, token_precedence)?;
+            height = if extends_chain {
+                height.max(self.height + 1)
+            } else {
+                height.max(self.height) + 1
+            };
+            if outer_depth + height > MAX_HEIGHT {
+                return Err(self.error("E0112", "The expression is too long!"));
+            }
+        }
+
+        self.depth = outer_depth;
+        self.height = outer_height.max(height);
+        Ok(left)
     }
-    Ok(None)
-}
 
-fn check_not_operator(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    if let Some(i) = tokens.iter().enumerate().position(|(index, t)| match t {
-        Token::Not => !is_in_codeblock(tokens, index) && !is_in_parentheses(tokens, index),
-        _ => false,
-    }) {
-        return Ok(Some(KodyNode::CallFunction {
-            function: Box::new(KodyNode::GetVariable {
-                name: String::from("__not"),
-            }),
-            arguments: vec![parse_expression_tokens(tokens.split_at(i + 1).1)?],
-        }));
+    fn nest(&mut self) -> Result<(), KodyError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("E0112", "The code is nested too deeply!"));
+        }
+        self.depth += 1;
+        Ok(())
     }
-    Ok(None)
-}
 
-// TODO idk if you can improve this
-fn check_function_call_and_member_access(tokens: &[Token]) -> Result<Option<KodyNode>, String> {
-    // _i for memberaccess
-    for (_i, token) in tokens.iter().rev().enumerate() {
-        match token {
-            Token::CloseParentheses => {
-                let mut indent_level = 1;
-                let mut open_parentheses_index = 0;
-                for (i, token) in tokens.iter().rev().enumerate().skip(1) {
-                    match token {
-                        Token::OpenParentheses => {
-                            indent_level -= 1;
-                            if indent_level == 0 {
-                                open_parentheses_index = tokens.len() - 1 - i;
-                                break;
-                            }
+    fn parse_prefix(&mut self) -> Result<KodyNode, KodyError> {
+        let start = self.here();
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(KodyError::parse("E0102", "Expected an expression!", start)),
+        };
+
+        Ok(match token {
+            Token::Identifier(name) => KodyNode::GetVariable {
+                name: name.clone(),
+                span: start,
+            },
+            Token::StringLiteral(value) => KodyNode::GetConstant {
+                value: KodyObject::from(KodyValue::StringLiteral(value.clone())),
+                span: start,
+            },
+            Token::FormatString(parts) => self.parse_format_string(parts, start)?,
+            Token::Number(val) => KodyNode::GetConstant {
+                value: KodyObject::from(KodyValue::Number(KodyNumber::parse(val).ok_or_else(
+                    || KodyError::parse("E0107", format!("{} is not a valid number!", val), start),
+                )?)),
+                span: start,
+            },
+            Token::True => KodyNode::GetConstant {
+                value: KodyObject::from(KodyValue::Bool(true)),
+                span: start,
+            },
+            Token::False => KodyNode::GetConstant {
+                value: KodyObject::from(KodyValue::Bool(false)),
+                span: start,
+            },
+            Token::OpenParentheses => {
+                let inner = self.parse_expression(LOWEST)?;
+                self.expect(&Token::CloseParentheses, "E0103", "Unclosed (")?;
+                inner
+            }
+            Token::OpenSquareBrackets => {
+                let items =
+                    self.parse_list(&Token::CloseSquareBrackets, "Expected , or ] in list!")?;
+                call_native("__list", items, self.span_from(start))
+            }
+            Token::OpenCurlyBrackets => self.parse_block_or_map(start)?,
+            Token::Subtract => {
+                let operand = self.parse_expression(NEGATION)?;
+                call_native("__negate", vec![operand], self.span_from(start))
+            }
+            Token::Not => {
+                let operand = self.parse_expression(NOT)?;
+                call_native("__not", vec![operand], self.span_from(start))
+            }
+            Token::If => self.parse_if(start)?,
+            Token::While => {
+                let condition = self.parse_expression(LOWEST)?;
+                self.loop_depth += 1;
+                let action = self.parse_action();
+                self.loop_depth -= 1;
+                let action = action?;
+                KodyNode::WhileStatement {
+                    condition: Box::new(condition),
+                    action: Box::new(action),
+                    span: self.span_from(start),
+                }
+            }
+            Token::For => {
+                let variable = match self.next() {
+                    Some(Token::Identifier(name)) => name.clone(),
+                    _ => {
+                        return Err(KodyError::parse(
+                            "E0110",
+                            "Expected a variable name after for!",
+                            self.span_from(start),
+                        ))
+                    }
+                };
+                self.expect(
+                    &Token::In,
+                    "E0110",
+                    "Expected in after the variable of a for loop!",
+                )?;
+                let iterable = self.parse_expression(LOWEST)?;
+                self.loop_depth += 1;
+                let action = self.parse_action();
+                self.loop_depth -= 1;
+                KodyNode::ForStatement {
+                    variable,
+                    iterable: Box::new(iterable),
+                    action: Box::new(action?),
+                    span: self.span_from(start),
+                }
+            }
+            Token::Return => {
+                let return_value = if self.starts_expression() {
+                    self.parse_expression(LOWEST)?
+                } else {
+                    KodyNode::GetConstant {
+                        value: KodyObject::from(KodyValue::Empty),
+                        span: start,
+                    }
+                };
+                KodyNode::ReturnFromFunction {
+                    return_value: Box::new(return_value),
+                    span: self.span_from(start),
+                }
+            }
+            Token::Break | Token::Continue if self.loop_depth == 0 => {
+                return Err(KodyError::parse(
+                    "E0109",
+                    format!(
+                        "{} outside of a loop!",
+                        if token == &Token::Break {
+                            "break"
+                        } else {
+                            "continue"
+                        }
+                    ),
+                    start,
+                ))
+            }
+            Token::Break => KodyNode::Break { span: start },
+            Token::Continue => KodyNode::Continue { span: start },
+            Token::FunctionDef if self.peek() == Some(&Token::OpenParentheses) => {
+                self.parse_lambda(start)?
+            }
+            Token::FunctionDef => {
+                return Err(KodyError::parse(
+                    "E0101",
+                    "Unfinished expression before function definition!",
+                    start,
+                ))
+            }
+            Token::Else | Token::Elif => {
+                return Err(KodyError::parse(
+                    "E0111",
+                    format!(
+                        "{} without an if before it!",
+                        if token == &Token::Else {
+                            "else"
+                        } else {
+                            "elif"
                         }
-                        Token::CloseParentheses => indent_level += 1,
-                        _ => (),
+                    ),
+                    start,
+                ))
+            }
+            other => {
+                return Err(KodyError::parse(
+                    "E0101",
+                    format!("Unexpected token {:?}", other),
+                    start,
+                ))
+            }
+        })
+    }
+
+    // f"a{x}b" becomes __format("a", x, "b"). The expressions are parsed
+    // on their own but in the same loops and at the same depth.
+    fn parse_format_string(
+        &mut self,
+        parts: &'a [FormatPart],
+        start: Span,
+    ) -> Result<KodyNode, KodyError> {
+        let mut arguments = vec![];
+        for part in parts {
+            match part {
+                FormatPart::Text(text) => arguments.push(KodyNode::GetConstant {
+                    value: KodyObject::from(KodyValue::StringLiteral(text.clone())),
+                    span: start,
+                }),
+                FormatPart::Expression { tokens, span } => {
+                    if tokens.is_empty() {
+                        return Err(KodyError::parse(
+                            "E0102",
+                            "Expected an expression between { and }!",
+                            *span,
+                        ));
+                    }
+                    let mut parser = Parser {
+                        tokens,
+                        position: 0,
+                        loop_depth: self.loop_depth,
+                        depth: self.depth,
+                        height: 0,
+                    };
+                    arguments.push(parser.parse_expression(LOWEST)?);
+                    self.height = self.height.max(parser.height);
+                    if parser.position < tokens.len() {
+                        return Err(parser.error(
+                            "E0101",
+                            "Expected } after the expression in a format string!",
+                        ));
                     }
                 }
+            }
+        }
+        Ok(call_native("__format", arguments, start))
+    }
 
-                if open_parentheses_index == 0 {
-                    return Err(String::from("Can't find pair for closing parentheses!"));
+    // The if has already been consumed. Any number of else if or elif
+    // branches and one else can follow the first branch.
+    fn parse_if(&mut self, start: Span) -> Result<KodyNode, KodyError> {
+        let mut branches = vec![self.parse_if_branch("if")?];
+        let mut else_action = None;
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(Token::Elif), _) => {
+                    self.next();
+                    branches.push(self.parse_if_branch("elif")?);
+                }
+                (Some(Token::Else), Some(Token::If)) => {
+                    self.position += 2;
+                    branches.push(self.parse_if_branch("else if")?);
                 }
+                (Some(Token::Else), _) => {
+                    self.next();
+                    if !self.starts_expression() {
+                        return Err(self.error("E0111", "Expected an action after else!"));
+                    }
+                    else_action = Some(Box::new(self.parse_action()?));
+                    break;
+                }
+                _ => break,
+            }
+        }
+        Ok(KodyNode::IfStatement {
+            branches,
+            else_action,
+            span: self.span_from(start),
+        })
+    }
 
-                let (function_tokens, mut argument_tokens) =
-                    tokens.split_at(open_parentheses_index);
+    fn parse_if_branch(&mut self, keyword: &str) -> Result<IfBranch, KodyError> {
+        if !self.starts_expression() {
+            return Err(self.error("E0111", &format!("Expected a condition after {}!", keyword)));
+        }
+        let condition = self.parse_expression(LOWEST)?;
+        if !self.starts_expression() {
+            return Err(self.error(
+                "E0111",
+                &format!("Expected an action after the condition of {}!", keyword),
+            ));
+        }
+        let action = self.parse_action()?;
+        Ok(IfBranch { condition, action })
+    }
 
-                argument_tokens = &argument_tokens[1..argument_tokens.len() - 1];
+    // A { starts a map if it is followed by } or an expression and a colon,
+    // otherwise it starts a code block. The first expression is parsed only
+    // once and becomes either the first key or the first statement.
+    fn parse_block_or_map(&mut self, start: Span) -> Result<KodyNode, KodyError> {
+        if self.peek() == Some(&Token::CloseCurlyBrackets) {
+            self.next();
+            return Ok(call_native("__map", vec![], self.span_from(start)));
+        }
 
-                let mut separator_indices = vec![];
+        let mut statements = vec![];
+        if self.starts_expression() {
+            // {} is an empty block unless it is the first key of a map
+            let first = match self.tokens.get(self.position + 2) {
+                Some(token) if token.token == Token::Colon => self.parse_expression(LOWEST)?,
+                _ => self.parse_action()?,
+            };
+            if self.peek() == Some(&Token::Colon) {
+                return self.parse_map(first, start);
+            }
+            statements.push(first);
+        }
 
-                indent_level = 0;
+        statements.extend(self.parse_statements(Some(&Token::CloseCurlyBrackets))?);
+        self.expect(&Token::CloseCurlyBrackets, "E0103", "Unclosed {")?;
+        Ok(KodyNode::CodeBlock {
+            statements,
+            span: self.span_from(start),
+        })
+    }
 
-                for (i, token) in argument_tokens.iter().enumerate() {
-                    match token {
-                        Token::OpenParentheses => indent_level += 1,
-                        Token::CloseParentheses => indent_level -= 1,
-                        Token::Separator => {
-                            if indent_level == 0 {
-                                separator_indices.push(i)
-                            }
-                        }
-                        _ => (),
-                    }
+    // Parses the rest of a map literal after its first key
+    fn parse_map(&mut self, first_key: KodyNode, start: Span) -> Result<KodyNode, KodyError> {
+        let mut items = vec![first_key];
+        loop {
+            self.expect(&Token::Colon, "E0101", "Expected : after map key!")?;
+            items.push(self.parse_expression(LOWEST)?);
+            match self.peek() {
+                Some(Token::Separator) => {
+                    self.next();
                 }
+                Some(Token::CloseCurlyBrackets) => {
+                    self.next();
+                    return Ok(call_native("__map", items, self.span_from(start)));
+                }
+                _ => return Err(self.error("E0101", "Expected , or } in map!")),
+            }
+            items.push(self.parse_expression(LOWEST)?);
+        }
+    }
 
-                let mut arguments = vec![];
-                if !argument_tokens.is_empty() {
-                    let mut last_index = 0;
-                    for index in separator_indices {
-                        arguments.push(parse_expression_tokens(
-                            &argument_tokens[last_index..index],
-                        )?);
+    fn starts_expression(&self) -> bool {
+        match self.peek() {
+            Some(token) => {
+                !matches!(
+                    token,
+                    Token::CloseParentheses
+                        | Token::CloseCurlyBrackets
+                        | Token::CloseSquareBrackets
+                        | Token::Separator
+                        | Token::Colon
+                        | Token::Else
+                        | Token::Elif
+                ) && (token != &Token::FunctionDef
+                    || self.peek_second() == Some(&Token::OpenParentheses))
+            }
+            None => false,
+        }
+    }
 
-                        last_index = index + 1;
+    fn parse_infix(&mut self, left: KodyNode, precedence: u8) -> Result<KodyNode, KodyError> {
+        // infix_precedence has already checked that the token exists
+        let operator = self.next().unwrap();
+        let start = left.span();
+
+        match operator {
+            Token::OpenParentheses => {
+                let arguments = self.parse_list(
+                    &Token::CloseParentheses,
+                    "Expected , or ) in function arguments!",
+                )?;
+                Ok(KodyNode::CallFunction {
+                    function: Box::new(left),
+                    arguments,
+                    span: self.span_from(start),
+                })
+            }
+            Token::OpenSquareBrackets => {
+                let index = self.parse_expression(LOWEST)?;
+                self.expect(&Token::CloseSquareBrackets, "E0103", "Unclosed [")?;
+                Ok(KodyNode::GetIndex {
+                    base: Box::new(left),
+                    index: Box::new(index),
+                    span: self.span_from(start),
+                })
+            }
+            Token::MemberAccess => match self.peek() {
+                Some(Token::Identifier(member)) => {
+                    self.next();
+                    Ok(KodyNode::GetMember {
+                        base: Box::new(left),
+                        member: member.clone(),
+                        span: self.span_from(start),
+                    })
+                }
+                _ => Err(self.error("E0101", "Expected a member name after .")),
+            },
+            Token::Assign
+            | Token::AddAssign
+            | Token::SubtractAssign
+            | Token::MultiplyAssign
+            | Token::DivideAssign => {
+                let value = self.parse_expression(precedence)?;
+                let span = self.span_from(start);
+                match left {
+                    KodyNode::GetVariable {
+                        name,
+                        span: name_span,
+                    } => {
+                        let value = if operator == &Token::Assign {
+                            value
+                        } else {
+                            call_native(
+                                binary_function_name(operator),
+                                vec![
+                                    KodyNode::GetVariable {
+                                        name: name.clone(),
+                                        span: name_span,
+                                    },
+                                    value,
+                                ],
+                                span,
+                            )
+                        };
+                        Ok(KodyNode::SetVariable {
+                            name,
+                            value: Box::new(value),
+                            span,
+                        })
+                    }
+                    KodyNode::GetMember { base, member, .. } => Ok(KodyNode::SetMember {
+                        base,
+                        member,
+                        operation: if operator == &Token::Assign {
+                            None
+                        } else {
+                            Some(binary_function_name(operator))
+                        },
+                        value: Box::new(value),
+                        span,
+                    }),
+                    KodyNode::GetIndex { base, index, .. } => {
+                        if operator == &Token::Assign {
+                            Ok(KodyNode::SetIndex {
+                                base,
+                                index,
+                                value: Box::new(value),
+                                span,
+                            })
+                        } else {
+                            Ok(KodyNode::UpdateIndex {
+                                base,
+                                index,
+                                operation: binary_function_name(operator),
+                                value: Box::new(value),
+                                span,
+                            })
+                        }
                     }
-                    arguments.push(parse_expression_tokens(
-                        &argument_tokens[last_index..argument_tokens.len()],
-                    )?);
+                    _ => Err(KodyError::parse(
+                        "E0104",
+                        "Cannot assign to something other than a variable, an index or a member!",
+                        start,
+                    )),
                 }
-
-                return Ok(Some(KodyNode::CallFunction {
-                    function: Box::new(parse_expression_tokens(function_tokens)?),
-                    arguments,
-                }));
             }
-
-            Token::MemberAccess => {
-                unimplemented!();
-                // see above row
-                /*if let Some(Token::Identifier(member_name)) = tokens.get(tokens.len() - i) {
-                    return Ok(Some(KodyNode::GetMember {
-                        base_object: Box::new(parse_expression_tokens(
-                            tokens.split_at(tokens.len() - i - 1).0,
-                        )?),
-                        member_name: member_name.clone(),
-                    }));
-                }*/
+            _ => {
+                if let (Token::Add, Some(Token::Add))
+                | (Token::Add, Some(Token::Subtract))
+                | (Token::Subtract, Some(Token::Add))
+                | (Token::Subtract, Some(Token::Subtract)) = (operator, self.peek())
+                {
+                    return Err(
+                        self.error("E0106", "Two consecutive addition or subtraction symbols")
+                    );
+                }
+                let operation = Operation {
+                    operator: binary_function_name(operator),
+                    operand: self.parse_expression(precedence)?,
+                    span: self.span_from(start),
+                };
+                // the operation is added to the chain left of it
+                Ok(match left {
+                    KodyNode::OperatorChain {
+                        first,
+                        mut operations,
+                        ..
+                    } => {
+                        operations.push(operation);
+                        KodyNode::OperatorChain {
+                            first,
+                            operations,
+                            span: self.span_from(start),
+                        }
+                    }
+                    left => KodyNode::OperatorChain {
+                        first: Box::new(left),
+                        operations: vec![operation],
+                        span: self.span_from(start),
+                    },
+                })
             }
-            _ => (),
         }
     }
-    Ok(None)
-}
 
-pub fn parse_expression_tokens(tokens: &[Token]) -> Result<KodyNode, String> {
-    assert!(!tokens.is_empty());
-
-    for check in &[
-        check_return,
-        check_if_expression,
-        check_while_expression,
-        check_negation,
-        check_value,
-        check_assignment,
-        check_comparison,
-        check_addition_and_subtraction,
-        check_multiplication_and_division,
-        check_or_operator,
-        check_and_operator,
-        check_not_operator,
-        check_parentheses,
-        check_codeblock,
-        check_function_call_and_member_access,
-    ] {
-        match check(tokens) {
-            Ok(Some(node)) => return Ok(node),
-            Err(e) => return Err(e),
-            Ok(None) => (),
+    // Parses comma separated expressions until the closing token
+    fn parse_list(&mut self, closing: &Token, error: &str) -> Result<Vec<KodyNode>, KodyError> {
+        let mut items = vec![];
+        if self.peek() == Some(closing) {
+            self.next();
+            return Ok(items);
+        }
+        loop {
+            items.push(self.parse_expression(LOWEST)?);
+            match self.peek() {
+                Some(Token::Separator) => {
+                    self.next();
+                }
+                Some(token) if token == closing => {
+                    self.next();
+                    return Ok(items);
+                }
+                _ => return Err(self.error("E0101", error)),
+            };
         }
     }
+}
 
-    unreachable!()
+#[cfg(test)]
+pub fn parse_expression_tokens(tokens: &[SpannedToken]) -> Result<KodyNode, KodyError> {
+    let mut parser = Parser::new(tokens);
+    let expression = parser.parse_expression(LOWEST)?;
+    match parser.peek() {
+        None => Ok(expression),
+        Some(token) => Err(parser.error(
+            "E0101",
+            &format!("Unexpected token {:?} after expression", token),
+        )),
+    }
 }
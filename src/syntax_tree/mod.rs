@@ -1,360 +1,224 @@
-use std::collections::HashMap;
+use std::rc::Rc;
 
-use crate::runtime::objects::{KodyObject, KodyValue};
-use crate::tokenizer::Token;
+use crate::error::KodyError;
+use crate::runtime::objects::KodyObject;
+use crate::span::Span;
+use crate::tokenizer::SpannedToken;
 
 mod expression_parser;
+#[cfg(test)]
 use expression_parser::parse_expression_tokens;
+use expression_parser::Parser;
 
+// Every node keeps the span of the source code it was parsed from
 #[derive(Debug, PartialEq, Clone)]
 pub enum KodyNode {
     WhileStatement {
         condition: Box<KodyNode>,
         action: Box<KodyNode>,
+        span: Span,
     },
-    IfStatement {
-        condition: Box<KodyNode>,
+    // for variable in iterable action, the variable is
+    // new in every iteration
+    ForStatement {
+        variable: String,
+        iterable: Box<KodyNode>,
         action: Box<KodyNode>,
+        span: Span,
+    },
+    // if a {..} else if b {..} else {..} is one node with a branch for
+    // every condition, the first branch whose condition is true runs
+    IfStatement {
+        branches: Vec<IfBranch>,
         else_action: Option<Box<KodyNode>>,
+        span: Span,
     },
     CodeBlock {
         statements: Vec<KodyNode>,
+        span: Span,
     },
     ReturnFromFunction {
         return_value: Box<KodyNode>,
+        span: Span,
+    },
+    // leave the innermost loop
+    Break {
+        span: Span,
+    },
+    // go on with the next iteration of the innermost loop
+    Continue {
+        span: Span,
     },
     GetConstant {
         value: KodyObject,
+        span: Span,
     },
     // Maybe make this more flexible in the future
     SetVariable {
         name: String,
         value: Box<KodyNode>,
+        span: Span,
     },
     CallFunction {
         function: Box<KodyNode>,
         arguments: Vec<KodyNode>,
+        span: Span,
+    },
+    // a + b * c - d and the like, evaluated from the left: every operation
+    // calls the native function of its operator with the value so far and
+    // its operand. A chain is one node, however long it is, so that it
+    // doesn't make the syntax tree deeper.
+    OperatorChain {
+        first: Box<KodyNode>,
+        operations: Vec<Operation>,
+        span: Span,
+    },
+    // obj.member
+    GetMember {
+        base: Box<KodyNode>,
+        member: String,
+        span: Span,
+    },
+    // obj.member = value, or obj.member += value when there is an operation
+    SetMember {
+        base: Box<KodyNode>,
+        member: String,
+        operation: Option<&'static str>,
+        value: Box<KodyNode>,
+        span: Span,
     },
-    // We aren't this advanced yet
-    /*GetMember {
-        base_object: Box<KodyNode>,
-        member_name: String,
-    },*/
     GetVariable {
         name: String,
+        span: Span,
+    },
+    // xs[i]
+    GetIndex {
+        base: Box<KodyNode>,
+        index: Box<KodyNode>,
+        span: Span,
     },
+    // xs[i] = value
+    SetIndex {
+        base: Box<KodyNode>,
+        index: Box<KodyNode>,
+        value: Box<KodyNode>,
+        span: Span,
+    },
+    // func name(arguments) body, the function is defined in the code
+    // block around it and can be called anywhere in that block
+    FunctionDefinition {
+        function: Rc<KodyFunctionData>,
+        span: Span,
+    },
+    // func(arguments) body, evaluates to a new function
+    Lambda {
+        function: Rc<KodyFunctionData>,
+        span: Span,
+    },
+    // xs[i] += value, the base and the index are evaluated only once
+    // and operation is the name of the native function to apply
+    UpdateIndex {
+        base: Box<KodyNode>,
+        index: Box<KodyNode>,
+        operation: &'static str,
+        value: Box<KodyNode>,
+        span: Span,
+    },
+}
+
+impl KodyNode {
+    pub fn span(&self) -> Span {
+        match self {
+            KodyNode::WhileStatement { span, .. }
+            | KodyNode::ForStatement { span, .. }
+            | KodyNode::IfStatement { span, .. }
+            | KodyNode::CodeBlock { span, .. }
+            | KodyNode::ReturnFromFunction { span, .. }
+            | KodyNode::Break { span }
+            | KodyNode::Continue { span }
+            | KodyNode::GetConstant { span, .. }
+            | KodyNode::SetVariable { span, .. }
+            | KodyNode::CallFunction { span, .. }
+            | KodyNode::OperatorChain { span, .. }
+            | KodyNode::GetMember { span, .. }
+            | KodyNode::SetMember { span, .. }
+            | KodyNode::GetVariable { span, .. }
+            | KodyNode::GetIndex { span, .. }
+            | KodyNode::SetIndex { span, .. }
+            | KodyNode::UpdateIndex { span, .. }
+            | KodyNode::FunctionDefinition { span, .. }
+            | KodyNode::Lambda { span, .. } => *span,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct KodySyntaxTree {
-    pub global_variables: HashMap<String, KodyObject>,
     pub main: KodyNode,
 }
 
+// if condition action, or else if / elif condition action
 #[derive(Debug, PartialEq, Clone)]
-pub struct KodyFunctionData {
-    pub name: String,
-    pub arguments: Vec<String>,
-    pub body: KodyNode,
-}
-
-pub fn parse_tokens(tokens: &[Token]) -> Result<KodySyntaxTree, String> {
-    let (function_tokens, remaining_tokens) = get_tokens_of_functions(tokens)?;
-
-    let mut functions = Vec::with_capacity(function_tokens.len());
-
-    for func_tokens in function_tokens {
-        functions.push(parse_function_tokens(&func_tokens)?);
-    }
-
-    if remaining_tokens.is_empty() {
-        return Err(String::from("No code besides function definitions"));
-    }
-
-    let main = parse_code_block(&remaining_tokens)?;
-    // make a hashmap out of functions
-    let global_variables = functions
-        .iter()
-        .map(|func_data| {
-            (
-                func_data.name.clone(),
-                KodyObject::from(KodyValue::Function(func_data.clone())),
-            )
-        })
-        .collect();
-
-    Ok(KodySyntaxTree {
-        global_variables,
-        main,
-    })
-}
-
-fn get_tokens_of_functions(tokens: &[Token]) -> Result<(Vec<Vec<Token>>, Vec<Token>), String> {
-    let mut functions = vec![];
-
-    let mut remaining_tokens = tokens.to_vec();
-    let mut contains = remaining_tokens.contains(&Token::FunctionDef);
-    while contains {
-        let (new_func, new_rem_tokens) = get_next_function_tokens(&remaining_tokens)?;
-        remaining_tokens = new_rem_tokens.to_vec();
-        functions.push(new_func);
-        contains = remaining_tokens.contains(&Token::FunctionDef)
-    }
-
-    Ok((functions, remaining_tokens))
-}
-
-fn parse_code_block(tokens: &[Token]) -> Result<KodyNode, String> {
-    let expressions = identify_expressions(tokens)?;
-    let mut statements = vec![];
-
-    for expression in expressions {
-        statements.push(parse_expression_tokens(expression)?);
-    }
-
-    Ok(KodyNode::CodeBlock { statements })
+pub struct IfBranch {
+    pub condition: KodyNode,
+    pub action: KodyNode,
 }
 
-fn get_if_expression_tokens(tokens: &[Token]) -> Result<(&[Token], &[Token]), String> {
-    if tokens[1..tokens.len()].is_empty() {
-        return Err(String::from("Expected tokens after if"));
-    }
-
-    let (condition, other) = get_next_expression(&tokens[1..tokens.len()])?;
-
-    if other.is_empty() {
-        return Err(String::from(
-            "Expected tokens after condition in if expression",
-        ));
-    }
-
-    let (action, other) = get_next_expression(&other)?;
-
-    if other.first() == Some(&Token::Else) {
-        if other.len() < 2 {
-            return Err(String::from("Expected tokens after else!"));
-        }
-
-        let (else_action, _) = get_next_expression(&other[1..other.len()])?;
-
-        return Ok(tokens.split_at(condition.len() + action.len() + else_action.len() + 2));
-    }
-
-    Ok(tokens.split_at(condition.len() + action.len() + 1))
+// one link of an operator chain, its span reaches from the start of the chain
+#[derive(Debug, PartialEq, Clone)]
+pub struct Operation {
+    pub operator: &'static str,
+    pub operand: KodyNode,
+    pub span: Span,
 }
 
-fn get_while_expression_tokens(tokens: &[Token]) -> Result<(&[Token], &[Token]), String> {
-    if tokens[1..tokens.len()].is_empty() {
-        return Err(String::from("Expected tokens after while!"));
-    }
-    let (condition, other) = get_next_expression(&tokens[1..tokens.len()])?;
-
-    if other.is_empty() {
-        return Err(String::from(
-            "Expected tokens after condition in while statement",
-        ));
-    }
-
-    let (action, _) = get_next_expression(&other)?;
-
-    Ok(tokens.split_at(condition.len() + action.len() + 1))
+#[derive(Debug, PartialEq, Clone)]
+pub struct KodyFunctionData {
+    pub name: String,
+    pub arguments: Vec<String>,
+    pub body: KodyNode,
 }
 
-fn parse_function_tokens(tokens: &[Token]) -> Result<KodyFunctionData, String> {
-    let name = if let Some(Token::Identifier(function_name)) = tokens.get(1) {
-        function_name.clone()
-    } else {
-        return Err(String::from("Expected identifier after function keyword!"));
-    };
-
-    let mut argument_iter = tokens.iter().skip(3);
-    let mut arguments = vec![];
-    let mut argument_len = 0;
-
-    match argument_iter.next() {
-        Some(Token::Identifier(name)) => {
-            arguments.push(name.clone());
-            argument_len += 1;
-        }
-        Some(Token::CloseParentheses) => (),
-        _ => {
-            return Err(String::from(
-                "Unexpexted token after ( in function definition!",
+pub fn parse_tokens(tokens: &[SpannedToken]) -> Result<KodySyntaxTree, KodyError> {
+    let syntax_tree = parse_input(tokens)?;
+    match &syntax_tree.main {
+        KodyNode::CodeBlock { statements, span }
+            if statements
+                .iter()
+                .all(|statement| matches!(statement, KodyNode::FunctionDefinition { .. })) =>
+        {
+            Err(KodyError::parse(
+                "E0108",
+                "No code besides function definitions",
+                *span,
             ))
         }
+        _ => Ok(syntax_tree),
     }
-
-    while match argument_iter.next() {
-        Some(&Token::Separator) => true,
-        Some(&Token::CloseParentheses) => false,
-        _ => return Err(String::from("Unexpexted token in function arguments!")),
-    } {
-        argument_len += 1;
-        if let Some(Token::Identifier(name)) = argument_iter.next() {
-            arguments.push(name.clone());
-        } else {
-            return Err(String::from("Unexpexted token in function arguments!"));
-        }
-    }
-
-    let body_tokens = get_next_expression(&tokens[4 + argument_len * 2 - 1..tokens.len()])?.0;
-
-    let body = parse_expression_tokens(body_tokens)?;
-
-    Ok(KodyFunctionData {
-        name,
-        arguments,
-        body,
-    })
 }
 
-fn get_next_function_tokens(tokens: &[Token]) -> Result<(Vec<Token>, Vec<Token>), String> {
-    let func_index = match tokens.iter().position(|t| t == &Token::FunctionDef) {
-        Some(index) => index,
-        None => return Ok((vec![], tokens.to_vec())),
-    };
-
-    let func_tokens = &tokens[func_index..tokens.len()];
-
-    if let Some(Token::OpenParentheses) = func_tokens.get(2) {
-    } else {
-        return Err(String::from(
-            "Expected parentheses after function identifier!",
-        ));
-    }
+// Like parse_tokens but the input can consist of only function definitions,
+// used for the inputs of an interactive session
+pub fn parse_input(tokens: &[SpannedToken]) -> Result<KodySyntaxTree, KodyError> {
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse_statements(None)?;
 
-    let argument_len = match func_tokens
-        .iter()
-        .skip(3)
-        .position(|t| t == &Token::CloseParentheses)
-    {
-        Some(length) => length,
-        None => {
-            return Err(String::from(
-                "Unclosed parentheses after function identifier!",
-            ))
-        }
+    let span = match (statements.first(), statements.last()) {
+        (Some(first), Some(last)) => first.span().to(last.span()),
+        _ => tokens.first().map_or(
+            Span {
+                start: 0,
+                end: 0,
+                line: 1,
+                column: 1,
+            },
+            |token| token.span,
+        ),
     };
 
-    let body_tokens = get_next_expression(&func_tokens[4 + argument_len..func_tokens.len()])?.0;
-
-    let body_len = body_tokens.len();
-
-    let total_len = argument_len + body_len + 4;
-
-    let mut remaining_tokens = tokens[0..func_index].to_vec();
-    remaining_tokens.extend_from_slice(&tokens[func_index + total_len..tokens.len()]);
-
-    Ok((
-        tokens[func_index..func_index + total_len].to_vec(),
-        remaining_tokens,
-    ))
-}
-
-fn get_next_expression(tokens: &[Token]) -> Result<(&[Token], &[Token]), String> {
-    assert!(!tokens.is_empty());
-
-    if tokens.first() == Some(&Token::If) {
-        return get_if_expression_tokens(&tokens);
-    }
-
-    if tokens.first() == Some(&Token::While) {
-        return get_while_expression_tokens(&tokens);
-    }
-
-    let mut token_iterator = tokens.iter().enumerate().peekable();
-
-    if tokens.first() == Some(&Token::Return) {
-        if tokens.len() == 1 {
-            return Ok(tokens.split_at(1));
-        }
-        // skip the return token and then return the following expression
-        token_iterator.next();
-    }
-
-    while let Some((i, token)) = token_iterator.next() {
-        match token {
-            Token::Identifier(_)
-            | Token::Number(_)
-            | Token::StringLiteral(_)
-            | Token::CloseParentheses
-            | Token::CloseCurlyBrackets
-            | Token::True
-            | Token::False => {
-                if let Token::Identifier(_)
-                | Token::Number(_)
-                | Token::StringLiteral(_)
-                | Token::OpenCurlyBrackets
-                | Token::If
-                | Token::While
-                | Token::Else
-                | Token::True
-                | Token::False
-                | Token::Return
-                | Token::FunctionDef = token_iterator.peek().unwrap_or(&(0, &Token::Empty)).1
-                {
-                    return Ok(tokens.split_at(i + 1));
-                }
-            }
-            Token::FunctionDef => {
-                return Err(String::from(
-                    "Unfinished expression before function definition!",
-                ));
-            }
-            Token::Else => {
-                return Err(String::from("Unexpexted else token"));
-            }
-            Token::Return => {
-                return Err(String::from("Unexpexted return token"));
-            }
-            Token::If | Token::While => {
-                for _ in 0..get_next_expression(&tokens[i..tokens.len()])?.0.len() - 2 {
-                    token_iterator.next();
-                }
-            }
-            Token::OpenCurlyBrackets => {
-                let mut indent_level = 0;
-                while match token_iterator.peek() {
-                    Some((_, Token::OpenCurlyBrackets)) => {
-                        indent_level += 1;
-                        true
-                    }
-                    Some((_, Token::CloseCurlyBrackets)) => {
-                        if indent_level == 0 {
-                            false
-                        } else {
-                            indent_level -= 1;
-                            true
-                        }
-                    }
-                    None => return Err(String::from("Unclosed {")),
-                    _ => true,
-                } {
-                    token_iterator.next();
-                }
-            }
-            _ => (),
-        }
-    }
-
-    Ok((tokens, &[]))
-}
-
-fn identify_expressions(tokens: &[Token]) -> Result<Vec<&[Token]>, String> {
-    let mut expressions = vec![];
-    let mut remaining_tokens = tokens;
-
-    // TODO can be made simpler when if let chains gets stabilized
-    #[allow(irrefutable_let_patterns)]
-    while let (expression, other_tokens) = get_next_expression(remaining_tokens)? {
-        expressions.push(expression);
-        if other_tokens.is_empty() {
-            break;
-        }
-        remaining_tokens = other_tokens;
-    }
-
-    Ok(expressions)
+    Ok(KodySyntaxTree {
+        main: KodyNode::CodeBlock { statements, span },
+    })
 }
 
 // TODO you can never have too many tests
@@ -362,99 +226,120 @@ fn identify_expressions(tokens: &[Token]) -> Result<Vec<&[Token]>, String> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::runtime::objects::KodyNumber;
+    use crate::runtime::objects::{KodyNumber, KodyValue};
+    use crate::tokenizer::{tokenize, Token};
+
+    fn spanned(tokens: &[Token]) -> Vec<SpannedToken> {
+        tokens
+            .iter()
+            .map(|token| SpannedToken {
+                token: token.clone(),
+                span: Span::default(),
+            })
+            .collect()
+    }
 
     #[test]
     fn negation_and_subtraction() {
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::Identifier(String::from("x")),
                 Token::Assign,
                 Token::Number(String::from("3")),
                 Token::Divide,
                 Token::Subtract,
                 Token::Number(String::from("5"))
-            ]),
+            ])),
             Ok(KodyNode::SetVariable {
                 name: String::from("x"),
-                value: Box::new(KodyNode::CallFunction {
-                    function: Box::new(KodyNode::GetVariable {
-                        name: String::from("__divide")
+                value: Box::new(KodyNode::OperatorChain {
+                    first: Box::new(KodyNode::GetConstant {
+                        value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(3))),
+                        span: Span::default()
                     }),
-                    arguments: vec![
-                        KodyNode::GetConstant {
-                            value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(3)))
-                        },
-                        KodyNode::CallFunction {
+                    operations: vec![Operation {
+                        operator: "__divide",
+                        operand: KodyNode::CallFunction {
                             function: Box::new(KodyNode::GetVariable {
-                                name: String::from("__negate")
+                                name: String::from("__negate"),
+                                span: Span::default()
                             }),
                             arguments: vec![KodyNode::GetConstant {
-                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(5)))
-                            }]
-                        }
-                    ]
-                })
+                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(5))),
+                                span: Span::default()
+                            }],
+                            span: Span::default()
+                        },
+                        span: Span::default()
+                    }],
+                    span: Span::default()
+                }),
+                span: Span::default()
             })
         );
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::Number(String::from("5")),
                 Token::Subtract,
                 Token::Number(String::from("3")),
-            ]),
-            Ok(KodyNode::CallFunction {
-                function: Box::new(KodyNode::GetVariable {
-                    name: String::from("__subtract")
+            ])),
+            Ok(KodyNode::OperatorChain {
+                first: Box::new(KodyNode::GetConstant {
+                    value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(5))),
+                    span: Span::default()
                 }),
-                arguments: vec![
-                    KodyNode::GetConstant {
-                        value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(5)))
+                operations: vec![Operation {
+                    operator: "__subtract",
+                    operand: KodyNode::GetConstant {
+                        value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(3))),
+                        span: Span::default()
                     },
-                    KodyNode::GetConstant {
-                        value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(3)))
-                    }
-                ]
+                    span: Span::default()
+                }],
+                span: Span::default()
             })
         );
-        assert!(parse_expression_tokens(&[
+        assert!(parse_expression_tokens(&spanned(&[
             Token::Number(String::from("3")),
             Token::Add,
             Token::Subtract,
             Token::Number(String::from("2"))
-        ])
+        ]))
         .is_err());
     }
 
     #[test]
     fn simple_expressions() {
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::Identifier(String::from("x")),
                 Token::Assign,
                 Token::Identifier(String::from("y")),
                 Token::Add,
                 Token::Number(String::from("1"))
-            ]),
+            ])),
             Ok(KodyNode::SetVariable {
                 name: String::from("x"),
-                value: Box::new(KodyNode::CallFunction {
-                    function: Box::new(KodyNode::GetVariable {
-                        name: String::from("__add")
+                value: Box::new(KodyNode::OperatorChain {
+                    first: Box::new(KodyNode::GetVariable {
+                        name: String::from("y"),
+                        span: Span::default()
                     }),
-                    arguments: vec![
-                        KodyNode::GetVariable {
-                            name: String::from("y")
-                        },
-                        KodyNode::GetConstant {
-                            value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(1)))
+                    operations: vec![Operation {
+                        operator: "__add",
+                        operand: KodyNode::GetConstant {
+                            value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(1))),
+                            span: Span::default()
                         },
-                    ]
-                })
+                        span: Span::default()
+                    }],
+                    span: Span::default()
+                }),
+                span: Span::default()
             })
         );
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::Identifier(String::from("print")),
                 Token::OpenParentheses,
                 Token::Identifier(String::from("y")),
@@ -463,29 +348,34 @@ mod test {
                 Token::Add,
                 Token::Number(String::from("2")),
                 Token::CloseParentheses,
-            ]),
+            ])),
             Ok(KodyNode::CallFunction {
                 function: Box::new(KodyNode::GetVariable {
-                    name: String::from("print")
+                    name: String::from("print"),
+                    span: Span::default()
                 }),
                 arguments: vec![
                     KodyNode::GetVariable {
-                        name: String::from("y")
+                        name: String::from("y"),
+                        span: Span::default()
                     },
-                    KodyNode::CallFunction {
-                        function: Box::new(KodyNode::GetVariable {
-                            name: String::from("__add")
+                    KodyNode::OperatorChain {
+                        first: Box::new(KodyNode::GetConstant {
+                            value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(1))),
+                            span: Span::default()
                         }),
-                        arguments: vec![
-                            KodyNode::GetConstant {
-                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(1)))
+                        operations: vec![Operation {
+                            operator: "__add",
+                            operand: KodyNode::GetConstant {
+                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(2))),
+                                span: Span::default()
                             },
-                            KodyNode::GetConstant {
-                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(2)))
-                            }
-                        ]
+                            span: Span::default()
+                        }],
+                        span: Span::default()
                     },
-                ]
+                ],
+                span: Span::default()
             })
         );
     }
@@ -493,7 +383,7 @@ mod test {
     #[test]
     fn parentheses() {
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::Identifier(String::from("a")),
                 Token::Multiply,
                 Token::OpenParentheses,
@@ -501,29 +391,32 @@ mod test {
                 Token::Subtract,
                 Token::Identifier(String::from("b")),
                 Token::CloseParentheses
-            ]),
-            Ok(KodyNode::CallFunction {
-                function: Box::new(KodyNode::GetVariable {
-                    name: String::from("__multiply")
+            ])),
+            Ok(KodyNode::OperatorChain {
+                first: Box::new(KodyNode::GetVariable {
+                    name: String::from("a"),
+                    span: Span::default()
                 }),
-                arguments: vec![
-                    KodyNode::GetVariable {
-                        name: String::from("a")
-                    },
-                    KodyNode::CallFunction {
-                        function: Box::new(KodyNode::GetVariable {
-                            name: String::from("__subtract")
+                operations: vec![Operation {
+                    operator: "__multiply",
+                    operand: KodyNode::OperatorChain {
+                        first: Box::new(KodyNode::GetConstant {
+                            value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(2))),
+                            span: Span::default()
                         }),
-                        arguments: vec![
-                            KodyNode::GetConstant {
-                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(2)))
+                        operations: vec![Operation {
+                            operator: "__subtract",
+                            operand: KodyNode::GetVariable {
+                                name: String::from("b"),
+                                span: Span::default()
                             },
-                            KodyNode::GetVariable {
-                                name: String::from("b")
-                            }
-                        ]
-                    }
-                ]
+                            span: Span::default()
+                        }],
+                        span: Span::default()
+                    },
+                    span: Span::default()
+                }],
+                span: Span::default()
             })
         );
     }
@@ -531,7 +424,7 @@ mod test {
     #[test]
     fn if_expression() {
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::Identifier(String::from("a")),
                 Token::Add,
                 Token::If,
@@ -542,37 +435,46 @@ mod test {
                 Token::Number(String::from("5")),
                 Token::Identifier(String::from("a")),
                 Token::CloseCurlyBrackets
-            ]),
-            Ok(KodyNode::CallFunction {
-                function: Box::new(KodyNode::GetVariable {
-                    name: String::from("__add")
+            ])),
+            Ok(KodyNode::OperatorChain {
+                first: Box::new(KodyNode::GetVariable {
+                    name: String::from("a"),
+                    span: Span::default()
                 }),
-                arguments: vec![
-                    KodyNode::GetVariable {
-                        name: String::from("a")
+                operations: vec![Operation {
+                    operator: "__add",
+                    operand: KodyNode::IfStatement {
+                        branches: vec![IfBranch {
+                            condition: KodyNode::GetConstant {
+                                value: KodyObject::from(KodyValue::Bool(true)),
+                                span: Span::default()
+                            },
+                            action: KodyNode::CodeBlock {
+                                statements: vec![
+                                    KodyNode::SetVariable {
+                                        name: String::from("a"),
+                                        value: Box::new(KodyNode::GetConstant {
+                                            value: KodyObject::from(KodyValue::Number(
+                                                KodyNumber::from_int(5)
+                                            )),
+                                            span: Span::default()
+                                        }),
+                                        span: Span::default()
+                                    },
+                                    KodyNode::GetVariable {
+                                        name: String::from("a"),
+                                        span: Span::default()
+                                    }
+                                ],
+                                span: Span::default()
+                            }
+                        }],
+                        else_action: None,
+                        span: Span::default()
                     },
-                    KodyNode::IfStatement {
-                        condition: Box::new(KodyNode::GetConstant {
-                            value: KodyObject::from(KodyValue::Bool(true))
-                        }),
-                        action: Box::new(KodyNode::CodeBlock {
-                            statements: vec![
-                                KodyNode::SetVariable {
-                                    name: String::from("a"),
-                                    value: Box::new(KodyNode::GetConstant {
-                                        value: KodyObject::from(KodyValue::Number(
-                                            KodyNumber::from_int(5)
-                                        ))
-                                    })
-                                },
-                                KodyNode::GetVariable {
-                                    name: String::from("a")
-                                }
-                            ]
-                        }),
-                        else_action: None
-                    }
-                ]
+                    span: Span::default()
+                }],
+                span: Span::default()
             })
         )
     }
@@ -580,7 +482,7 @@ mod test {
     #[test]
     fn logic_operators() {
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::Not,
                 Token::OpenParentheses,
                 Token::True,
@@ -589,34 +491,38 @@ mod test {
                 Token::Or,
                 Token::True,
                 Token::CloseParentheses
-            ]),
+            ])),
             Ok(KodyNode::CallFunction {
                 function: Box::new(KodyNode::GetVariable {
-                    name: String::from("__not")
+                    name: String::from("__not"),
+                    span: Span::default()
                 }),
-                arguments: vec![KodyNode::CallFunction {
-                    function: Box::new(KodyNode::GetVariable {
-                        name: String::from("__or")
+                arguments: vec![KodyNode::OperatorChain {
+                    first: Box::new(KodyNode::GetConstant {
+                        value: KodyObject::from(KodyValue::Bool(true)),
+                        span: Span::default()
                     }),
-                    arguments: vec![
-                        KodyNode::CallFunction {
-                            function: Box::new(KodyNode::GetVariable {
-                                name: String::from("__and")
-                            }),
-                            arguments: vec![
-                                KodyNode::GetConstant {
-                                    value: KodyObject::from(KodyValue::Bool(true))
-                                },
-                                KodyNode::GetConstant {
-                                    value: KodyObject::from(KodyValue::Bool(false))
-                                }
-                            ]
+                    operations: vec![
+                        Operation {
+                            operator: "__and",
+                            operand: KodyNode::GetConstant {
+                                value: KodyObject::from(KodyValue::Bool(false)),
+                                span: Span::default()
+                            },
+                            span: Span::default()
                         },
-                        KodyNode::GetConstant {
-                            value: KodyObject::from(KodyValue::Bool(true))
+                        Operation {
+                            operator: "__or",
+                            operand: KodyNode::GetConstant {
+                                value: KodyObject::from(KodyValue::Bool(true)),
+                                span: Span::default()
+                            },
+                            span: Span::default()
                         }
-                    ]
-                }]
+                    ],
+                    span: Span::default()
+                }],
+                span: Span::default()
             })
         )
     }
@@ -624,7 +530,7 @@ mod test {
     #[test]
     fn control_flow() {
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::If,
                 Token::Identifier(String::from("y")),
                 Token::Equals,
@@ -633,34 +539,42 @@ mod test {
                 Token::OpenParentheses,
                 Token::Identifier(String::from("y")),
                 Token::CloseParentheses
-            ]),
+            ])),
             Ok(KodyNode::IfStatement {
-                condition: Box::new(KodyNode::CallFunction {
-                    function: Box::new(KodyNode::GetVariable {
-                        name: String::from("__equal")
-                    }),
-                    arguments: vec![
-                        KodyNode::GetVariable {
-                            name: String::from("y")
-                        },
-                        KodyNode::GetConstant {
-                            value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(1)))
-                        }
-                    ]
-                }),
-                action: Box::new(KodyNode::CallFunction {
-                    function: Box::new(KodyNode::GetVariable {
-                        name: String::from("print")
-                    }),
-                    arguments: vec![KodyNode::GetVariable {
-                        name: String::from("y")
-                    }]
-                }),
-                else_action: None
+                branches: vec![IfBranch {
+                    condition: KodyNode::OperatorChain {
+                        first: Box::new(KodyNode::GetVariable {
+                            name: String::from("y"),
+                            span: Span::default()
+                        }),
+                        operations: vec![Operation {
+                            operator: "__equal",
+                            operand: KodyNode::GetConstant {
+                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(1))),
+                                span: Span::default()
+                            },
+                            span: Span::default()
+                        }],
+                        span: Span::default()
+                    },
+                    action: KodyNode::CallFunction {
+                        function: Box::new(KodyNode::GetVariable {
+                            name: String::from("print"),
+                            span: Span::default()
+                        }),
+                        arguments: vec![KodyNode::GetVariable {
+                            name: String::from("y"),
+                            span: Span::default()
+                        }],
+                        span: Span::default()
+                    }
+                }],
+                else_action: None,
+                span: Span::default()
             })
         );
         assert_eq!(
-            parse_expression_tokens(&[
+            parse_expression_tokens(&spanned(&[
                 Token::While,
                 Token::Identifier(String::from("check")),
                 Token::OpenParentheses,
@@ -674,15 +588,18 @@ mod test {
                 Token::Assign,
                 Token::Number(String::from("2")),
                 Token::CloseCurlyBrackets,
-            ]),
+            ])),
             Ok(KodyNode::WhileStatement {
                 condition: Box::new(KodyNode::CallFunction {
                     function: Box::new(KodyNode::GetVariable {
-                        name: String::from("check")
+                        name: String::from("check"),
+                        span: Span::default()
                     }),
                     arguments: vec![KodyNode::GetVariable {
-                        name: String::from("x")
+                        name: String::from("x"),
+                        span: Span::default()
                     }],
+                    span: Span::default(),
                 }),
                 action: Box::new(KodyNode::CodeBlock {
                     statements: vec![
@@ -690,27 +607,632 @@ mod test {
                             name: String::from("x"),
                             value: Box::new(KodyNode::CallFunction {
                                 function: Box::new(KodyNode::GetVariable {
-                                    name: String::from("__divide")
+                                    name: String::from("__divide"),
+                                    span: Span::default()
                                 }),
                                 arguments: vec![
                                     KodyNode::GetVariable {
-                                        name: String::from("x")
+                                        name: String::from("x"),
+                                        span: Span::default()
                                     },
                                     KodyNode::GetVariable {
-                                        name: String::from("y")
+                                        name: String::from("y"),
+                                        span: Span::default()
                                     }
-                                ]
-                            })
+                                ],
+                                span: Span::default()
+                            }),
+                            span: Span::default()
                         },
                         KodyNode::SetVariable {
                             name: String::from("y"),
                             value: Box::new(KodyNode::GetConstant {
-                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(2)))
-                            })
+                                value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(2))),
+                                span: Span::default()
+                            }),
+                            span: Span::default()
                         }
-                    ]
+                    ],
+                    span: Span::default()
+                }),
+                span: Span::default()
+            })
+        );
+    }
+
+    // parses source code without keeping the spans of the tokens
+    fn parse(source: &str) -> Result<KodyNode, KodyError> {
+        let tokens = tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect::<Vec<_>>();
+        parse_expression_tokens(&spanned(&tokens))
+    }
+
+    fn number(value: i64) -> KodyNode {
+        KodyNode::GetConstant {
+            value: KodyObject::from(KodyValue::Number(KodyNumber::from_int(value))),
+            span: Span::default(),
+        }
+    }
+
+    fn call(name: &str, arguments: Vec<KodyNode>) -> KodyNode {
+        KodyNode::CallFunction {
+            function: Box::new(KodyNode::GetVariable {
+                name: String::from(name),
+                span: Span::default(),
+            }),
+            arguments,
+            span: Span::default(),
+        }
+    }
+
+    fn chain(first: KodyNode, operations: Vec<(&'static str, KodyNode)>) -> KodyNode {
+        KodyNode::OperatorChain {
+            first: Box::new(first),
+            operations: operations
+                .into_iter()
+                .map(|(operator, operand)| Operation {
+                    operator,
+                    operand,
+                    span: Span::default(),
                 })
+                .collect(),
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn left_associativity() {
+        assert_eq!(
+            parse("10 - 3 - 2"),
+            Ok(chain(
+                number(10),
+                vec![("__subtract", number(3)), ("__subtract", number(2))]
+            ))
+        );
+        assert_eq!(
+            parse("8 / 4 / 2"),
+            Ok(chain(
+                number(8),
+                vec![("__divide", number(4)), ("__divide", number(2))]
+            ))
+        );
+        // a chain in parentheses is an operand of its own
+        assert_eq!(
+            parse("8 / (4 / 2)"),
+            Ok(chain(
+                number(8),
+                vec![("__divide", chain(number(4), vec![("__divide", number(2))]))]
+            ))
+        );
+    }
+
+    #[test]
+    fn operator_precedence() {
+        assert_eq!(
+            parse("1 < 2 or 3 + 4 * 5 > 6"),
+            // the looser or takes the rest as its operand, the tighter *
+            // makes a chain of its own
+            Ok(chain(
+                number(1),
+                vec![
+                    ("__less_than", number(2)),
+                    (
+                        "__or",
+                        chain(
+                            number(3),
+                            vec![
+                                ("__add", chain(number(4), vec![("__multiply", number(5))])),
+                                ("__greater_than", number(6))
+                            ]
+                        )
+                    )
+                ]
+            ))
+        );
+        assert_eq!(
+            parse("-2 * 3"),
+            Ok(chain(
+                call("__negate", vec![number(2)]),
+                vec![("__multiply", number(3))]
+            ))
+        );
+    }
+
+    fn variable(name: &str) -> KodyNode {
+        KodyNode::GetVariable {
+            name: String::from(name),
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            parse("[1, [], x][0]"),
+            Ok(KodyNode::GetIndex {
+                base: Box::new(call(
+                    "__list",
+                    vec![number(1), call("__list", vec![]), variable("x")]
+                )),
+                index: Box::new(number(0)),
+                span: Span::default(),
             })
         );
+        assert_eq!(
+            parse("xs[i] = 1 + 2"),
+            Ok(KodyNode::SetIndex {
+                base: Box::new(variable("xs")),
+                index: Box::new(variable("i")),
+                value: Box::new(chain(number(1), vec![("__add", number(2))])),
+                span: Span::default(),
+            })
+        );
+        assert_eq!(
+            parse("xs[0][1] -= 2"),
+            Ok(KodyNode::UpdateIndex {
+                base: Box::new(KodyNode::GetIndex {
+                    base: Box::new(variable("xs")),
+                    index: Box::new(number(0)),
+                    span: Span::default(),
+                }),
+                index: Box::new(number(1)),
+                operation: "__subtract",
+                value: Box::new(number(2)),
+                span: Span::default(),
+            })
+        );
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("[1 2]").is_err());
+        assert!(parse("xs[1").is_err());
+        assert!(parse("f()[0] = 1").is_ok());
+        assert!(parse("f() = 1").is_err());
+    }
+
+    #[test]
+    fn maps_and_blocks() {
+        assert_eq!(
+            parse("{\"a\": 1, x: {}}"),
+            Ok(call(
+                "__map",
+                vec![
+                    KodyNode::GetConstant {
+                        value: KodyObject::from(KodyValue::StringLiteral(String::from("a"))),
+                        span: Span::default(),
+                    },
+                    number(1),
+                    variable("x"),
+                    call("__map", vec![])
+                ]
+            ))
+        );
+        assert_eq!(
+            parse("{x y}"),
+            Ok(KodyNode::CodeBlock {
+                statements: vec![variable("x"), variable("y")],
+                span: Span::default(),
+            })
+        );
+        assert_eq!(
+            parse("{func f() {} f}"),
+            Ok(KodyNode::CodeBlock {
+                statements: vec![
+                    KodyNode::FunctionDefinition {
+                        function: Rc::new(KodyFunctionData {
+                            name: String::from("f"),
+                            arguments: vec![],
+                            body: KodyNode::CodeBlock {
+                                statements: vec![],
+                                span: Span::default(),
+                            },
+                        }),
+                        span: Span::default(),
+                    },
+                    variable("f")
+                ],
+                span: Span::default(),
+            })
+        );
+        // {} is an empty block where a statement or an action is expected
+        let empty_block = KodyNode::CodeBlock {
+            statements: vec![],
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse("while x {}"),
+            Ok(KodyNode::WhileStatement {
+                condition: Box::new(variable("x")),
+                action: Box::new(empty_block.clone()),
+                span: Span::default(),
+            })
+        );
+        assert_eq!(
+            parse("{{} x}"),
+            Ok(KodyNode::CodeBlock {
+                statements: vec![empty_block, variable("x")],
+                span: Span::default(),
+            })
+        );
+        assert_eq!(
+            parse("{{}: 1}"),
+            Ok(call("__map", vec![call("__map", vec![]), number(1)]))
+        );
+        assert_eq!(
+            parse("x = {}"),
+            Ok(KodyNode::SetVariable {
+                name: String::from("x"),
+                value: Box::new(call("__map", vec![])),
+                span: Span::default(),
+            })
+        );
+        assert!(parse("{1: 2 3: 4}").is_err());
+        assert!(parse("{1: 2, 3}").is_err());
+        assert!(parse("{1 2: 3}").is_err());
+    }
+
+    #[test]
+    fn lambdas() {
+        let lambda = |arguments: &[&str], body| KodyNode::Lambda {
+            function: Rc::new(KodyFunctionData {
+                name: String::from("lambda"),
+                arguments: arguments.iter().map(|name| name.to_string()).collect(),
+                body,
+            }),
+            span: Span::default(),
+        };
+        let return_value = |value| KodyNode::ReturnFromFunction {
+            return_value: Box::new(value),
+            span: Span::default(),
+        };
+
+        assert_eq!(
+            parse("func(x) x"),
+            Ok(lambda(&["x"], return_value(variable("x"))))
+        );
+        // only an expression at the end of the body is returned
+        assert_eq!(
+            parse("func(x, y) { y = x y }"),
+            Ok(lambda(
+                &["x", "y"],
+                KodyNode::CodeBlock {
+                    statements: vec![
+                        KodyNode::SetVariable {
+                            name: String::from("y"),
+                            value: Box::new(variable("x")),
+                            span: Span::default(),
+                        },
+                        return_value(variable("y"))
+                    ],
+                    span: Span::default(),
+                }
+            ))
+        );
+        assert_eq!(
+            parse("func() {}(1)"),
+            Ok(KodyNode::CallFunction {
+                function: Box::new(lambda(
+                    &[],
+                    KodyNode::CodeBlock {
+                        statements: vec![],
+                        span: Span::default(),
+                    }
+                )),
+                arguments: vec![number(1)],
+                span: Span::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn member_access() {
+        let get_member = |base, member: &str| KodyNode::GetMember {
+            base: Box::new(base),
+            member: String::from(member),
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse("a.b.c(1)"),
+            Ok(KodyNode::CallFunction {
+                function: Box::new(get_member(get_member(variable("a"), "b"), "c")),
+                arguments: vec![number(1)],
+                span: Span::default(),
+            })
+        );
+        assert_eq!(
+            parse("a.b *= 2"),
+            Ok(KodyNode::SetMember {
+                base: Box::new(variable("a")),
+                member: String::from("b"),
+                operation: Some("__multiply"),
+                value: Box::new(number(2)),
+                span: Span::default(),
+            })
+        );
+        assert!(parse("a.").is_err());
+        assert!(parse("a.(b)").is_err());
+    }
+
+    #[test]
+    fn long_script() {
+        let source = "x = 0\n".to_string() + &"x = (x + 1) * 2 - x / 3\n".repeat(10_000);
+        let tree = parse_tokens(&tokenize(&source).unwrap()).unwrap();
+        if let KodyNode::CodeBlock { statements, .. } = tree.main {
+            assert_eq!(statements.len(), 10_001);
+        } else {
+            panic!("main was not a code block");
+        }
+    }
+
+    #[test]
+    fn spans() {
+        let node = parse_expression_tokens(&tokenize("x = f(1,\n  y)").unwrap()).unwrap();
+        assert_eq!(
+            node.span(),
+            Span {
+                start: 0,
+                end: 13,
+                line: 1,
+                column: 1
+            }
+        );
+        if let KodyNode::SetVariable { value, .. } = node {
+            assert_eq!(
+                value.span(),
+                Span {
+                    start: 4,
+                    end: 13,
+                    line: 1,
+                    column: 5
+                }
+            );
+        } else {
+            panic!("expected an assignment");
+        }
+        let error = parse_tokens(&tokenize("x = 1\nif x {\n  y = (\n}").unwrap()).unwrap_err();
+        assert_eq!(error.code(), "E0101");
+        assert_eq!(error.message(), "Unexpected token CloseCurlyBrackets");
+        assert_eq!(
+            error.span(),
+            Some(Span {
+                start: 21,
+                end: 22,
+                line: 4,
+                column: 1
+            })
+        );
+    }
+
+    #[test]
+    fn loop_control() {
+        let parse = |source| parse_tokens(&tokenize(source).unwrap());
+        assert!(parse("while true { if x { break } continue }").is_ok());
+        assert!(parse("while true while false break").is_ok());
+
+        let error = parse("x = 1\nbreak").unwrap_err();
+        assert_eq!(error.code(), "E0109");
+        assert_eq!(error.message(), "break outside of a loop!");
+        assert_eq!(error.span().map(|span| span.line), Some(2));
+        // a function body is not part of the loop around its definition
+        let error = parse("while true { f = func() { continue } f() }").unwrap_err();
+        assert_eq!(error.code(), "E0109");
+        assert_eq!(error.message(), "continue outside of a loop!");
+        assert!(parse("for x in xs { if x { continue } break }").is_ok());
+        assert_eq!(
+            parse("for x in range(0, 1) {} continue")
+                .unwrap_err()
+                .code(),
+            "E0109"
+        );
+        assert_eq!(parse("for 1 in xs {}").unwrap_err().code(), "E0110");
+        assert_eq!(parse("for x xs {}").unwrap_err().code(), "E0110");
+        // after the loop ends break is not allowed anymore
+        assert_eq!(parse("while false {} break").unwrap_err().code(), "E0109");
+    }
+
+    #[test]
+    fn if_chains() {
+        let branch = |condition: &str, value| IfBranch {
+            condition: variable(condition),
+            action: number(value),
+        };
+        assert_eq!(
+            parse("if a 1 else if b 2 elif c 3 else 4"),
+            Ok(KodyNode::IfStatement {
+                branches: vec![branch("a", 1), branch("b", 2), branch("c", 3)],
+                else_action: Some(Box::new(number(4))),
+                span: Span::default()
+            })
+        );
+        // an if in a block after else is not part of the chain
+        assert_eq!(
+            parse("if a 1 else { if b 2 }"),
+            Ok(KodyNode::IfStatement {
+                branches: vec![branch("a", 1)],
+                else_action: Some(Box::new(KodyNode::CodeBlock {
+                    statements: vec![KodyNode::IfStatement {
+                        branches: vec![branch("b", 2)],
+                        else_action: None,
+                        span: Span::default()
+                    }],
+                    span: Span::default()
+                })),
+                span: Span::default()
+            })
+        );
+
+        let parse = |source| parse_tokens(&tokenize(source).unwrap());
+        let error = |source| {
+            let error = parse(source).unwrap_err();
+            (error.code(), error.message().to_string())
+        };
+        assert!(parse("if a {\n} else if b {\n} else if c {\n} else {\n}").is_ok());
+        assert_eq!(
+            error("{ if }"),
+            ("E0111", String::from("Expected a condition after if!"))
+        );
+        assert_eq!(
+            error("if a {} else if"),
+            ("E0111", String::from("Expected a condition after else if!"))
+        );
+        assert_eq!(
+            error("{ if a }"),
+            (
+                "E0111",
+                String::from("Expected an action after the condition of if!")
+            )
+        );
+        assert_eq!(
+            error("if a {} elif b"),
+            (
+                "E0111",
+                String::from("Expected an action after the condition of elif!")
+            )
+        );
+        assert_eq!(
+            error("if a {} else"),
+            ("E0111", String::from("Expected an action after else!"))
+        );
+        assert_eq!(
+            error("if a {} else {} else {}"),
+            ("E0111", String::from("else without an if before it!"))
+        );
+        assert_eq!(
+            error("x = 1\nelif x {}"),
+            ("E0111", String::from("elif without an if before it!"))
+        );
+        let error = parse("if a {}\nelse").unwrap_err();
+        assert_eq!(error.span().map(|span| span.line), Some(2));
+    }
+
+    #[test]
+    fn format_strings() {
+        let tree = parse_tokens(&tokenize("x = 1\nf\"a{x + 1}\"").unwrap()).unwrap();
+        let statements = match tree.main {
+            KodyNode::CodeBlock { statements, .. } => statements,
+            _ => panic!("main was not a code block"),
+        };
+        match &statements[1] {
+            KodyNode::CallFunction {
+                function,
+                arguments,
+                ..
+            } => {
+                assert!(
+                    matches!(function.as_ref(), KodyNode::GetVariable { name, .. } if name == "__format")
+                );
+                assert!(matches!(
+                    &arguments[0],
+                    KodyNode::GetConstant { value, .. } if value.value.to_string() == "a"
+                ));
+                // the expression keeps its place in the source
+                assert_eq!(
+                    arguments[1].span(),
+                    Span {
+                        start: 10,
+                        end: 15,
+                        line: 2,
+                        column: 5
+                    }
+                );
+            }
+            _ => panic!("expected a call of __format"),
+        }
+
+        let error = |source| parse_tokens(&tokenize(source).unwrap()).unwrap_err();
+        let empty = error("x = 1\nf\"{}\"");
+        assert_eq!(empty.code(), "E0102");
+        assert_eq!(
+            empty.span().map(|span| (span.line, span.column)),
+            Some((2, 3))
+        );
+        assert_eq!(error("f\"{1 2}\"").code(), "E0101");
+        assert_eq!(error("f\"{break}\"").code(), "E0109");
+        assert!(parse_tokens(&tokenize("while true f\"{break}\"").unwrap()).is_ok());
+        // the expressions count towards the nesting of the code around them
+        let nested = format!(
+            "{}f\"{{{}}}\"{}",
+            "(".repeat(40),
+            "(".repeat(40),
+            ")".repeat(40)
+        );
+        assert_eq!(error(&nested).code(), "E0112");
+    }
+
+    // malformed programs give errors instead of panicking
+    #[test]
+    fn long_expressions() {
+        // a + b + c is one flat chain, however long it is
+        let sum = |terms: usize| format!("x = 1{}", " + 1 * 2".repeat(terms - 1));
+        assert!(parse(&sum(300)).is_ok());
+        assert!(parse(&sum(20_000)).is_ok());
+        assert!(parse(&format!("{}{}", "(".repeat(50), sum(100) + &")".repeat(50))).is_ok());
+        // members and calls still put the first one below the next
+        let error = parse(&format!("x{}", ".y".repeat(10_000))).unwrap_err();
+        assert_eq!(error.code(), "E0112");
+        assert_eq!(error.message(), "The expression is too long!");
+    }
+
+    #[test]
+    fn malformed_programs() {
+        let parse = |source: &str| tokenize(source).and_then(|tokens| parse_tokens(&tokens));
+        for source in &[
+            "x =",
+            "(",
+            "+",
+            ")",
+            "}",
+            "]",
+            "x.",
+            "x.1",
+            "f(,",
+            "[1,",
+            "{1:",
+            "{1: 2,",
+            "func",
+            "func f",
+            "func f(",
+            "func f(x,",
+            "func f(x)",
+            "func(",
+            "a[",
+            "a[1] +=",
+            "1 = 2",
+            "1 - - 1",
+            "1 + + 1",
+            "while",
+            "for",
+            "for x",
+            "for x in",
+            "else",
+            "elif",
+            "return +",
+            "\"\\U+\"",
+        ] {
+            assert!(parse(source).is_err(), "{} was parsed", source);
+        }
+        let error = parse(&"(".repeat(100)).unwrap_err();
+        assert_eq!(error.code(), "E0112");
+
+        // random sequences of tokens, generated with a fixed xorshift seed
+        let tokens = tokenize(
+            "func f(x, y) { return x } ( ) [ ] { } , : . = += - * / == < >= and or not \
+             if else elif while for in break continue return true false x 1 \"s\" f\"{x}\"",
+        )
+        .unwrap();
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..5000 {
+            let length = (state % 40) as usize;
+            let program = (0..length)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    tokens[(state % tokens.len() as u64) as usize].clone()
+                })
+                .collect::<Vec<_>>();
+            let _ = parse_tokens(&program);
+        }
     }
 }
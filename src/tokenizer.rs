@@ -1,11 +1,17 @@
 use std::iter::Peekable;
-use std::str::Chars;
+use std::mem;
+use std::str::CharIndices;
+
+use crate::error::KodyError;
+use crate::span::Span;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token {
     Identifier(String),
     Number(String),
     StringLiteral(String),
+    // f"text {expression} text"
+    FormatString(Vec<FormatPart>),
     Add,
     Subtract,
     Multiply,
@@ -19,15 +25,22 @@ pub enum Token {
     CloseParentheses,
     OpenCurlyBrackets,
     CloseCurlyBrackets,
+    OpenSquareBrackets,
+    CloseSquareBrackets,
     If,
     Else,
+    Elif,
     While,
+    For,
+    In,
     And,
     Or,
     Not,
     True,
     False,
     Return,
+    Break,
+    Continue,
     FunctionDef,
     Equals,
     NotEqual,
@@ -37,29 +50,133 @@ pub enum Token {
     LessThanOrEqual,
     MemberAccess,
     Separator,
+    Colon,
     Empty,
 }
 
-pub fn tokenize(filedata: &str) -> Result<Vec<Token>, String> {
-    let mut char_iter = filedata.chars().peekable();
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatPart {
+    Text(String),
+    // the tokens between { and }, the span includes the brackets
+    Expression {
+        tokens: Vec<SpannedToken>,
+        span: Span,
+    },
+}
+
+// how deeply format strings can be nested in the expressions of each other
+const MAX_FORMAT_DEPTH: usize = 16;
+
+// Keeps track of the position of the next character in the source
+struct Cursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    source_len: usize,
+    line: usize,
+    column: usize,
+    // how many format strings the cursor is in
+    format_depth: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str) -> Cursor<'a> {
+        Cursor {
+            chars: source.char_indices().peekable(),
+            source_len: source.len(),
+            line: 1,
+            column: 1,
+            format_depth: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let (_, character) = self.chars.next()?;
+        if character == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(character)
+    }
+
+    // the span of the next character
+    fn here(&mut self) -> Span {
+        let start = self.chars.peek().map_or(self.source_len, |(i, _)| *i);
+        Span {
+            start,
+            end: start + self.peek().map_or(0, char::len_utf8),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    // the span from start to the current position
+    fn span_from(&mut self, start: Span) -> Span {
+        let end = self.here().start;
+        Span { end, ..start }
+    }
+}
+
+pub fn tokenize(filedata: &str) -> Result<Vec<SpannedToken>, KodyError> {
+    tokenize_until(&mut Cursor::new(filedata), None)
+}
 
+// Tokenizes until the end of the input, or when expression_start is the
+// span of the { of an expression in a format string, until the } that
+// closes the expression
+fn tokenize_until(
+    cursor: &mut Cursor,
+    expression_start: Option<Span>,
+) -> Result<Vec<SpannedToken>, KodyError> {
     let mut tokens = vec![];
+    // the { that have been opened inside the expression
+    let mut open_brackets = 0;
 
     // iterate over all characters in the code
-    while let Some(character) = char_iter.next() {
+    loop {
+        let start = cursor.here();
+        let character = match cursor.next() {
+            Some(character) => character,
+            None => break,
+        };
+
         let token = match character {
-            '_' | 'A'..='Z' | 'a'..='z' => tokenize_identifier(character, &mut char_iter),
-            '0'..='9' => tokenize_number(character, &mut char_iter)?,
-            '"' => tokenize_string(&mut char_iter)?,
+            'f' if cursor.peek() == Some('"') => {
+                cursor.next();
+                tokenize_format_string(start, cursor)?
+            }
+            '_' | 'A'..='Z' | 'a'..='z' => tokenize_identifier(character, cursor),
+            '0'..='9' => Token::Number(tokenize_number(character, cursor)?),
+            '"' => tokenize_string(start, cursor)?,
             '(' => Token::OpenParentheses,
             ')' => Token::CloseParentheses,
-            '{' => Token::OpenCurlyBrackets,
-            '}' => Token::CloseCurlyBrackets,
+            '{' => {
+                open_brackets += 1;
+                Token::OpenCurlyBrackets
+            }
+            '}' if expression_start.is_some() && open_brackets == 0 => return Ok(tokens),
+            '}' => {
+                open_brackets = usize::saturating_sub(open_brackets, 1);
+                Token::CloseCurlyBrackets
+            }
+            '[' => Token::OpenSquareBrackets,
+            ']' => Token::CloseSquareBrackets,
             ',' => Token::Separator,
+            ':' => Token::Colon,
             '.' => Token::MemberAccess,
             '#' => {
                 // comment until the end of the line
-                for c in &mut char_iter {
+                while let Some(c) = cursor.next() {
                     if c == '\n' {
                         break;
                     }
@@ -67,11 +184,11 @@ pub fn tokenize(filedata: &str) -> Result<Vec<Token>, String> {
                 Token::Empty
             }
             ' ' | '\t' | '\r' | '\n' => Token::Empty,
-            _ => match char_iter.peek() {
+            _ => match cursor.peek() {
                 // check if there is a = character after the current character
                 // for example +=
-                Some(&'=') => {
-                    char_iter.next();
+                Some('=') => {
+                    cursor.next();
                     match character {
                         '+' => Token::AddAssign,
                         '-' => Token::SubtractAssign,
@@ -82,9 +199,10 @@ pub fn tokenize(filedata: &str) -> Result<Vec<Token>, String> {
                         '<' => Token::LessThanOrEqual,
                         '>' => Token::GreaterThanOrEqual,
                         _ => {
-                            return Err(format!(
-                                "Could not match character {:?} to any token",
-                                character
+                            return Err(KodyError::lex(
+                                "E0001",
+                                format!("Could not match character {:?} to any token", character),
+                                start,
                             ));
                         }
                     }
@@ -100,9 +218,10 @@ pub fn tokenize(filedata: &str) -> Result<Vec<Token>, String> {
                     '>' => Token::GreaterThan,
                     '-' => Token::Subtract,
                     _ => {
-                        return Err(format!(
-                            "Could not match character {:?} to any token",
-                            character
+                        return Err(KodyError::lex(
+                            "E0001",
+                            format!("Could not match character {:?} to any token", character),
+                            start,
                         ));
                     }
                 },
@@ -111,23 +230,36 @@ pub fn tokenize(filedata: &str) -> Result<Vec<Token>, String> {
 
         // discard any redundant tokens
         if token != Token::Empty {
-            tokens.push(token);
+            tokens.push(SpannedToken {
+                token,
+                span: cursor.span_from(start),
+            });
         }
     }
 
-    Ok(tokens)
+    match expression_start {
+        Some(start) => Err(KodyError::lex(
+            "E0005",
+            "Expected } after the expression in a format string!",
+            start,
+        )),
+        None => Ok(tokens),
+    }
 }
 
-fn tokenize_identifier(first_char: char, char_iter: &mut Peekable<Chars>) -> Token {
+fn tokenize_identifier(first_char: char, cursor: &mut Cursor) -> Token {
     let mut data = first_char.to_string();
-    while let Some('_') | Some('A'..='Z') | Some('a'..='z') | Some('0'..='9') = char_iter.peek() {
-        data.push(char_iter.next().unwrap());
+    while let Some('_') | Some('A'..='Z') | Some('a'..='z') | Some('0'..='9') = cursor.peek() {
+        data.push(cursor.next().unwrap());
     }
 
     match data.as_str() {
         "if" => Token::If,
         "else" => Token::Else,
+        "elif" => Token::Elif,
         "while" => Token::While,
+        "for" => Token::For,
+        "in" => Token::In,
         "and" => Token::And,
         "or" => Token::Or,
         "not" => Token::Not,
@@ -135,29 +267,65 @@ fn tokenize_identifier(first_char: char, char_iter: &mut Peekable<Chars>) -> Tok
         "false" => Token::False,
         "func" => Token::FunctionDef,
         "return" => Token::Return,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
         _ => Token::Identifier(data),
     }
 }
 
-fn tokenize_number(first_char: char, char_iter: &mut Peekable<Chars>) -> Result<Token, String> {
+// Reads text that has to be a number literal and nothing else,
+// so that num("3.25") reads numbers the way the source does
+pub fn tokenize_number_literal(text: &str) -> Result<String, KodyError> {
+    let mut cursor = Cursor::new(text);
+    let start = cursor.here();
+    let data = match cursor.next() {
+        Some(first_char @ '0'..='9') => tokenize_number(first_char, &mut cursor)?,
+        _ => {
+            return Err(KodyError::lex(
+                "E0002",
+                "A number has to start with a digit!",
+                start,
+            ))
+        }
+    };
+    if cursor.peek().is_some() {
+        return Err(KodyError::lex(
+            "E0002",
+            "Found something other than a digit in a number!",
+            cursor.here(),
+        ));
+    }
+    Ok(data)
+}
+
+// returns the digits of the number without underscores or extra zeroes
+fn tokenize_number(first_char: char, cursor: &mut Cursor) -> Result<String, KodyError> {
     let mut data = first_char.to_string();
     let mut has_decimals = false;
 
-    while match char_iter.peek() {
+    while match cursor.peek() {
         Some('_') | Some('0'..='9') => true,
         Some('A'..='Z') | Some('a'..='z') => {
-            return Err(String::from("Found an alphabetical character in a number!"))
+            return Err(KodyError::lex(
+                "E0002",
+                "Found an alphabetical character in a number!",
+                cursor.here(),
+            ))
         }
         Some('.') => {
             if has_decimals {
-                return Err(String::from("Multiple decimal separators in one number!"));
+                return Err(KodyError::lex(
+                    "E0002",
+                    "Multiple decimal separators in one number!",
+                    cursor.here(),
+                ));
             }
             has_decimals = true;
             true
         }
         _ => false,
     } {
-        data.push(char_iter.next().unwrap());
+        data.push(cursor.next().unwrap());
     }
 
     // erase underscores
@@ -180,74 +348,160 @@ fn tokenize_number(first_char: char, char_iter: &mut Peekable<Chars>) -> Result<
         data.insert(0, '0');
     }
 
-    Ok(Token::Number(data))
+    Ok(data)
 }
 
-fn tokenize_string(char_iter: &mut Peekable<Chars>) -> Result<Token, String> {
+fn tokenize_string(start: Span, cursor: &mut Cursor) -> Result<Token, KodyError> {
     let mut data = String::new();
-    while let Some(character) = char_iter.next() {
+    loop {
+        let escape_start = cursor.here();
+        let character = match cursor.next() {
+            Some(character) => character,
+            None => break,
+        };
         match character {
             // we have reached the end of the string
             '"' => return Ok(Token::StringLiteral(data)),
-            '\\' => {
-                match char_iter.next() {
-                    Some('\\') => data.push('\\'),
-                    Some('n') => data.push('\n'),
-                    Some('\'') => data.push('\''),
-                    Some('"') => data.push('"'),
-                    Some('U') => {
-                        if let Some('+') = char_iter.next() {
-                            let mut hex_input = String::new();
-                            while let Some('0'..='9') | Some('a'..='f') | Some('A'..='F') =
-                                char_iter.peek()
-                            {
-                                hex_input.push(char_iter.next().unwrap());
-                            }
-                            // this should always have valid input
-                            let char_code = u32::from_str_radix(&hex_input, 16).unwrap();
-                            if let Some(c) = std::char::from_u32(char_code) {
-                                data.push(c);
-                            } else {
-                                return Err(format!(
-                                    "Invalid unicode character code {}!",
-                                    char_code
-                                ));
-                            }
-                        } else {
-                            return Err(String::from(
-                                "Unicode literals need to be of the form \\U+xxxx",
-                            ));
-                        }
-                    }
+            '\\' => tokenize_escape(escape_start, cursor, &mut data)?,
+            _ => data.push(character),
+        }
+    }
+    Err(KodyError::lex("E0004", "String literal not closed", start))
+}
 
-                    // skip newline
-                    Some('\n') => (),
-                    _ => {
-                        return Err(String::from(
-                            "Expected any of \\, n, \', \" or U after escape character \\!",
-                        ));
+// f"fib({i}) = {b}", the f and the " have already been consumed.
+// {{ and }} are a { and a } in the text.
+fn tokenize_format_string(start: Span, cursor: &mut Cursor) -> Result<Token, KodyError> {
+    if cursor.format_depth == MAX_FORMAT_DEPTH {
+        return Err(KodyError::lex(
+            "E0005",
+            "Format strings are nested too deeply!",
+            start,
+        ));
+    }
+    let mut parts = vec![];
+    let mut text = String::new();
+    loop {
+        let character_start = cursor.here();
+        let character = match cursor.next() {
+            Some(character) => character,
+            None => return Err(KodyError::lex("E0004", "String literal not closed", start)),
+        };
+        match character {
+            '"' => break,
+            '{' | '}' if cursor.peek() == Some(character) => {
+                cursor.next();
+                text.push(character);
+            }
+            '{' => {
+                if !text.is_empty() {
+                    parts.push(FormatPart::Text(mem::take(&mut text)));
+                }
+                cursor.format_depth += 1;
+                let tokens = tokenize_until(cursor, Some(character_start));
+                cursor.format_depth -= 1;
+                parts.push(FormatPart::Expression {
+                    tokens: tokens?,
+                    span: cursor.span_from(character_start),
+                });
+            }
+            '}' => {
+                return Err(KodyError::lex(
+                    "E0005",
+                    "A } in a format string has to be written as }}!",
+                    character_start,
+                ))
+            }
+            '\\' => tokenize_escape(character_start, cursor, &mut text)?,
+            _ => text.push(character),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(FormatPart::Text(text));
+    }
+    Ok(Token::FormatString(parts))
+}
+
+// adds the character of the escape sequence after a \ to data
+fn tokenize_escape(
+    escape_start: Span,
+    cursor: &mut Cursor,
+    data: &mut String,
+) -> Result<(), KodyError> {
+    match cursor.next() {
+        Some('\\') => data.push('\\'),
+        Some('n') => data.push('\n'),
+        Some('\'') => data.push('\''),
+        Some('"') => data.push('"'),
+        Some('U') => {
+            if let Some('+') = cursor.next() {
+                let mut hex_input = String::new();
+                while let Some('0'..='9') | Some('a'..='f') | Some('A'..='F') = cursor.peek() {
+                    hex_input.push(cursor.next().unwrap());
+                }
+                if hex_input.is_empty() {
+                    return Err(KodyError::lex(
+                        "E0003",
+                        "Unicode literals need to be of the form \\U+xxxx",
+                        cursor.span_from(escape_start),
+                    ));
+                }
+                // too many digits don't fit in a u32 and are not a character either
+                match u32::from_str_radix(&hex_input, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                {
+                    Some(c) => data.push(c),
+                    None => {
+                        return Err(KodyError::lex(
+                            "E0003",
+                            format!("Invalid unicode character code U+{}!", hex_input),
+                            cursor.span_from(escape_start),
+                        ))
                     }
-                };
+                }
+            } else {
+                return Err(KodyError::lex(
+                    "E0003",
+                    "Unicode literals need to be of the form \\U+xxxx",
+                    cursor.span_from(escape_start),
+                ));
             }
-            _ => data.push(character),
+        }
+
+        // skip newline
+        Some('\n') => (),
+        _ => {
+            return Err(KodyError::lex(
+                "E0003",
+                "Expected any of \\, n, \', \" or U after escape character \\!",
+                cursor.span_from(escape_start),
+            ));
         }
     }
-    Err(String::from("String literal not closed"))
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn tokens(source: &str) -> Vec<Token> {
+        tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect()
+    }
+
     #[test]
     fn comments() {
         assert_eq!(
-            tokenize(
+            tokens(
                 "# this is a test to see if the 
         #tokenizer correctly igores # comments
         +"
-            )
-            .unwrap(),
+            ),
             vec![Token::Add]
         );
     }
@@ -255,7 +509,7 @@ mod test {
     #[test]
     fn identifiers() {
         assert_eq!(
-            tokenize("id = value").unwrap(),
+            tokens("id = value"),
             vec![
                 Token::Identifier(String::from("id")),
                 Token::Assign,
@@ -267,7 +521,7 @@ mod test {
     #[test]
     fn operators() {
         assert_eq!(
-            tokenize("+-  / * =").unwrap(),
+            tokens("+-  / * ="),
             vec![
                 Token::Add,
                 Token::Subtract,
@@ -281,7 +535,7 @@ mod test {
     #[test]
     fn numbers() {
         assert_eq!(
-            tokenize("12, 0000_25_._300, 0.0, 2., 0, 120").unwrap(),
+            tokens("12, 0000_25_._300, 0.0, 2., 0, 120"),
             vec![
                 Token::Number(String::from("12")),
                 Token::Separator,
@@ -298,6 +552,66 @@ mod test {
         );
     }
 
+    #[test]
+    fn number_literals() {
+        let literal =
+            |text| tokenize_number_literal(text).map_err(|error| error.message().to_string());
+        assert_eq!(literal("3.250"), Ok(String::from("3.25")));
+        assert_eq!(literal("1_000"), Ok(String::from("1000")));
+        assert!(literal("").is_err());
+        assert!(literal(".5").is_err());
+        assert!(literal("1.2.3").is_err());
+        assert!(literal("12a").is_err());
+        assert!(literal("1 2").is_err());
+    }
+
+    #[test]
+    fn format_strings() {
+        let tokens = tokenize("f\"{x}! {{\\n\"").unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(
+            tokens[0].token,
+            Token::FormatString(vec![
+                FormatPart::Expression {
+                    tokens: vec![SpannedToken {
+                        token: Token::Identifier(String::from("x")),
+                        span: Span {
+                            start: 3,
+                            end: 4,
+                            line: 1,
+                            column: 4
+                        }
+                    }],
+                    span: Span {
+                        start: 2,
+                        end: 5,
+                        line: 1,
+                        column: 3
+                    }
+                },
+                FormatPart::Text(String::from("! {\n")),
+            ])
+        );
+
+        // the expressions can contain strings, maps and other format strings
+        let tokens = tokenize("f\"{ {\"a\": f\"{1}\"} }\" f").unwrap();
+        assert_eq!(tokens.len(), 2);
+        if let Token::FormatString(parts) = &tokens[0].token {
+            assert!(
+                matches!(&parts[..], [FormatPart::Expression { tokens, .. }] if tokens.len() == 5)
+            );
+        } else {
+            panic!("expected a format string");
+        }
+        assert_eq!(tokens[1].token, Token::Identifier(String::from("f")));
+
+        let error = |source| tokenize(source).unwrap_err().code();
+        assert_eq!(error("f\"a } b\""), "E0005");
+        assert_eq!(error("f\"{1 + 2"), "E0005");
+        assert_eq!(error("f\"{1}"), "E0004");
+        assert_eq!(error(&"f\"{".repeat(20)), "E0005");
+    }
+
     #[test]
     fn errors() {
         assert!(tokenize("0000_.25_._300").is_err());
@@ -306,12 +620,15 @@ mod test {
         assert!(tokenize("\" this is an unclosed string # not a comment").is_err());
         assert!(tokenize(" \" unused \\ in a string literal \" ").is_err());
         assert!(tokenize("\"\\U+1021fFF\"").is_err());
+        assert!(tokenize("\"\\U+\"").is_err());
+        assert!(tokenize("\"\\U+123456789ABCDEF\"").is_err());
+        assert!(tokenize("\"\\U+D800\"").is_err());
     }
 
     #[test]
     fn member_access() {
         assert_eq!(
-            tokenize("x.y").unwrap(),
+            tokens("x.y"),
             vec![
                 Token::Identifier(String::from("x")),
                 Token::MemberAccess,
@@ -320,15 +637,47 @@ mod test {
         );
     }
 
+    #[test]
+    fn lists() {
+        assert_eq!(
+            tokens("xs = [1, x][0]"),
+            vec![
+                Token::Identifier(String::from("xs")),
+                Token::Assign,
+                Token::OpenSquareBrackets,
+                Token::Number(String::from("1")),
+                Token::Separator,
+                Token::Identifier(String::from("x")),
+                Token::CloseSquareBrackets,
+                Token::OpenSquareBrackets,
+                Token::Number(String::from("0")),
+                Token::CloseSquareBrackets
+            ]
+        );
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(
+            tokens("{\"a\": 1}"),
+            vec![
+                Token::OpenCurlyBrackets,
+                Token::StringLiteral(String::from("a")),
+                Token::Colon,
+                Token::Number(String::from("1")),
+                Token::CloseCurlyBrackets
+            ]
+        );
+    }
+
     #[test]
     fn functions() {
         assert_eq!(
-            tokenize(
+            tokens(
                 "func add(x, y) {
             return x + y
             }"
-            )
-            .unwrap(),
+            ),
             vec![
                 Token::FunctionDef,
                 Token::Identifier(String::from("add")),
@@ -350,13 +699,12 @@ mod test {
     #[test]
     fn logic_operators() {
         assert_eq!(
-            tokenize(
+            tokens(
                 "false or true and if not false 
             true 
         else 
             true or false"
-            )
-            .unwrap(),
+            ),
             vec![
                 Token::False,
                 Token::Or,
@@ -377,14 +725,13 @@ mod test {
     #[test]
     fn strings() {
         assert_eq!(
-            tokenize(
+            tokens(
                 "
             \"this is a string \\\non one line\"
              \"\\\"\\\'\\n\\\\\" 
              \"\\U+4B\\U+3B6\\U+2764\\U+1F4af\"
              "
-            )
-            .unwrap(),
+            ),
             vec![
                 Token::StringLiteral(String::from("this is a string on one line")),
                 Token::StringLiteral(String::from("\"\'\n\\")),
@@ -392,4 +739,58 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn spans() {
+        assert_eq!(
+            tokenize("x = \"ζ\"\n  12.5").unwrap(),
+            vec![
+                SpannedToken {
+                    token: Token::Identifier(String::from("x")),
+                    span: Span {
+                        start: 0,
+                        end: 1,
+                        line: 1,
+                        column: 1
+                    }
+                },
+                SpannedToken {
+                    token: Token::Assign,
+                    span: Span {
+                        start: 2,
+                        end: 3,
+                        line: 1,
+                        column: 3
+                    }
+                },
+                SpannedToken {
+                    token: Token::StringLiteral(String::from("ζ")),
+                    span: Span {
+                        start: 4,
+                        end: 8,
+                        line: 1,
+                        column: 5
+                    }
+                },
+                SpannedToken {
+                    token: Token::Number(String::from("12.5")),
+                    span: Span {
+                        start: 11,
+                        end: 15,
+                        line: 2,
+                        column: 3
+                    }
+                },
+            ]
+        );
+        assert_eq!(
+            tokenize("a\n  12b").unwrap_err().span(),
+            Some(Span {
+                start: 6,
+                end: 7,
+                line: 2,
+                column: 5
+            })
+        );
+    }
 }
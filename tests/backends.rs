@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::fs;
+use std::process::{Command, Output};
+use std::rc::Rc;
+
+use kody::{check_syntax, run_with_output, Arguments, Backend, KodyError, SourceType};
+
+fn run_kody(path: &str, extra_arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_kody"))
+        .arg(path)
+        .args(extra_arguments)
+        .output()
+        .expect("failed to run kody")
+}
+
+// the tree walker is the reference, the bytecode vm must behave the same way
+#[test]
+fn examples_match_between_backends() {
+    let mut paths = fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some(std::ffi::OsStr::new("kd")))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let path = path.to_str().unwrap();
+        let bytecode = run_kody(path, &[]);
+        let tree_walker = run_kody(path, &["--tree-walker"]);
+
+        assert_eq!(
+            bytecode.status.code(),
+            tree_walker.status.code(),
+            "{}",
+            path
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&bytecode.stdout),
+            String::from_utf8_lossy(&tree_walker.stdout),
+            "{}",
+            path
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&bytecode.stderr),
+            String::from_utf8_lossy(&tree_walker.stderr),
+            "{}",
+            path
+        );
+    }
+}
+
+fn run_source(source: &str, backend: Backend) -> Result<(), KodyError> {
+    run_source_with_output(source, backend).0
+}
+
+// the result of the program and what it printed
+fn run_source_with_output(source: &str, backend: Backend) -> (Result<(), KodyError>, String) {
+    let arguments = Arguments {
+        source: SourceType::Text(source.to_string()),
+        is_verbose: false,
+        ignore_extensions: false,
+        backend,
+    };
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let result = run_with_output(source, &arguments, output.clone());
+    let output = String::from_utf8(output.take()).unwrap();
+    (result, output)
+}
+
+// operator chains are flat, so their length is only limited by memory
+#[test]
+fn long_operator_chains() {
+    let source = format!("x = 0{}\nprint(x)", " + 1 * 2 - 1".repeat(10_000));
+    for backend in &[Backend::Bytecode, Backend::TreeWalker] {
+        assert_eq!(
+            run_source_with_output(&source, *backend),
+            (Ok(()), String::from("10000\n")),
+            "{:?}",
+            backend
+        );
+    }
+}
+
+// a variable that is assigned later hides the native function or constant
+// of its name only from then on
+#[test]
+fn globals_until_assigned() {
+    let source = "
+        func g() { return len([1]) }
+        print(g())
+        len = 5
+        print(len)
+        func h() { return pi }
+        print(h())
+        pi = 3
+        print(h())
+        if true { e = 1 }
+        print(e > 2)
+    ";
+    for backend in &[Backend::Bytecode, Backend::TreeWalker] {
+        assert_eq!(
+            run_source_with_output(source, *backend),
+            (Ok(()), String::from("1\n5\n3.141592653589793\n3\ntrue\n")),
+            "{:?}",
+            backend
+        );
+    }
+}
+
+// the errors that a golden script can't show, as every script stops at its first one
+#[test]
+fn errors_match_between_backends() {
+    let errors = [
+        (
+            "for x in range(0) 1",
+            "E0205",
+            "range takes 2 or 3 argument(s) but 1 were given!",
+        ),
+        (
+            "x = \"a\" * 0.5",
+            "E0205",
+            "Cannot repeat a string 0.5 times!",
+        ),
+        (
+            "x = \"ab\" * 1000000000000000000",
+            "E0205",
+            "The repeated string would be longer than 67108864 bytes!",
+        ),
+    ];
+    for (source, code, message) in &errors {
+        let error = run_source(source, Backend::Bytecode).unwrap_err();
+        assert_eq!(
+            Err(error.clone()),
+            run_source(source, Backend::TreeWalker),
+            "{}",
+            source
+        );
+        assert_eq!(
+            (error.code(), error.message()),
+            (*code, *message),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn deep_recursion_is_an_error() {
+    // runs on the default stack of a test thread, which the interpreter
+    // grows when it runs short
+    let sources = [
+        "func f(n) { return f(n + 1) } f(0)",
+        // the calls of native functions run in a loop of their own
+        "func f(n) { return [n].map(func(x) f(x + 1)) } f(0)",
+        "func f(n) { return [n].map(f) } f(0)",
+    ];
+    for source in &sources {
+        let error = run_source(source, Backend::Bytecode).unwrap_err();
+        assert!(matches!(error, KodyError::Runtime(_)), "{}", source);
+        assert_eq!(error.code(), "E0209", "{}", source);
+        assert_eq!(
+            Err(error),
+            run_source(source, Backend::TreeWalker),
+            "{}",
+            source
+        );
+    }
+
+    let source = "func down(n) { if n == 0 { return 0 } return down(n - 1) } down(900)";
+    assert_eq!(run_source(source, Backend::Bytecode), Ok(()));
+    assert_eq!(run_source(source, Backend::TreeWalker), Ok(()));
+}
+
+// the deepest code the parser allows can still be compiled and run
+#[test]
+fn deep_nesting() {
+    let shapes = [
+        ("(", "1", ")"),
+        ("[", "", "]"),
+        ("-(", "1", ")"),
+        ("{", "x = 1", "}"),
+        ("if true {", "x = 1", "}"),
+        ("while false {", "", "}"),
+        ("for i in [1] {", "", "}"),
+        ("func() { return ", "1", "}"),
+        ("x = ", "1", ""),
+        ("", "x", "(1)"),
+        ("", "x", ".y"),
+        ("f(", "", ")"),
+    ];
+    for (open, inner, close) in &shapes {
+        // a long sum in the innermost expression doesn't make the tree taller
+        let nested = |depth: usize, terms: usize| {
+            let sum = if inner.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", inner, " + x".repeat(terms))
+            };
+            format!(
+                "f = func(x) x\nx = {{}}\nreturn {}{}{}",
+                open.repeat(depth),
+                sum,
+                close.repeat(depth)
+            )
+        };
+        let depth = (1..)
+            .find(|&depth| check_syntax(&nested(depth, 0)).is_err())
+            .unwrap();
+        assert_eq!(check_syntax(&nested(depth, 0)).unwrap_err().code(), "E0112");
+        let source = nested(depth - 1, 1000);
+        assert_eq!(
+            run_source(&source, Backend::Bytecode),
+            run_source(&source, Backend::TreeWalker),
+            "{}{}{}",
+            open,
+            inner,
+            close
+        );
+    }
+}
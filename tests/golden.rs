@@ -0,0 +1,145 @@
+// Runs every .kd script in the script directories with both backends and
+// compares what it does with the files next to it:
+//   name.expected        what the script prints
+//   name.expected_error  the error it fails with, without it the script
+//                        has to succeed
+// Run with UPDATE_EXPECTED=1 to write the files from the current output.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SCRIPT_DIRECTORIES: [&str; 2] = ["examples", "tests/scripts"];
+
+// what running a script did, as the text that gets compared
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    success: bool,
+}
+
+fn run_script(path: &Path, extra_arguments: &[&str]) -> Outcome {
+    let output = Command::new(env!("CARGO_BIN_EXE_kody"))
+        .arg(path)
+        .args(extra_arguments)
+        .output()
+        .expect("failed to run kody");
+    Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        success: output.status.success(),
+    }
+}
+
+fn scripts() -> Vec<PathBuf> {
+    let mut paths = SCRIPT_DIRECTORIES
+        .iter()
+        .flat_map(|directory| fs::read_dir(directory).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some(std::ffi::OsStr::new("kd")))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+// the lines that differ, prefixed with - when expected and + when found
+fn diff(expected: &str, found: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let found = found.lines().collect::<Vec<_>>();
+    let mut result = String::new();
+    for i in 0..expected.len().max(found.len()) {
+        let (expected_line, found_line) = (expected.get(i), found.get(i));
+        if expected_line == found_line {
+            continue;
+        }
+        result += &format!("  line {}:\n", i + 1);
+        if let Some(line) = expected_line {
+            result += &format!("  - {}\n", line);
+        }
+        if let Some(line) = found_line {
+            result += &format!("  + {}\n", line);
+        }
+    }
+    if result.is_empty() {
+        // only the final newline differs
+        result += "  the output differs in its last newline\n";
+    }
+    result
+}
+
+// None if the outcome matches the expected files, else what went wrong
+fn check(path: &Path, outcome: &Outcome) -> Option<String> {
+    let output_path = path.with_extension("expected");
+    let error_path = path.with_extension("expected_error");
+
+    if env::var_os("UPDATE_EXPECTED").is_some() {
+        if outcome.success || !outcome.stdout.is_empty() {
+            fs::write(&output_path, &outcome.stdout).unwrap();
+        } else {
+            let _ = fs::remove_file(&output_path);
+        }
+        if outcome.success {
+            let _ = fs::remove_file(&error_path);
+        } else {
+            fs::write(&error_path, &outcome.stderr).unwrap();
+        }
+        return None;
+    }
+
+    let expected_output = fs::read_to_string(&output_path).ok();
+    let expected_error = fs::read_to_string(&error_path).ok();
+    let mut problems = String::new();
+
+    match &expected_error {
+        None if expected_output.is_none() => {
+            problems += "has no .expected or .expected_error file\n";
+        }
+        None if !outcome.success => {
+            problems += &format!("failed unexpectedly:\n{}", outcome.stderr);
+        }
+        Some(_) if outcome.success => problems += "succeeded but should have failed\n",
+        Some(error) if *error != outcome.stderr => {
+            problems += &format!(
+                "failed with another error:\n{}",
+                diff(error, &outcome.stderr)
+            );
+        }
+        _ => (),
+    }
+    // a script that fails can still pin what it printed before
+    if let Some(output) = &expected_output {
+        if *output != outcome.stdout {
+            problems += &format!("printed other output:\n{}", diff(output, &outcome.stdout));
+        }
+    }
+
+    if problems.is_empty() {
+        None
+    } else {
+        Some(problems)
+    }
+}
+
+#[test]
+fn scripts_match_expected_output() {
+    let paths = scripts();
+    assert!(!paths.is_empty());
+
+    let mut failures = String::new();
+    for path in &paths {
+        for (backend, arguments) in [
+            ("bytecode", &[][..]),
+            ("tree walker", &["--tree-walker"][..]),
+        ] {
+            let outcome = run_script(path, arguments);
+            if let Some(failure) = check(path, &outcome) {
+                failures += &format!("{} ({}) {}\n", path.display(), backend, failure);
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!("\n{}", failures);
+    }
+}
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use kody::{FromKody, Interpreter, IntoKody, KodyError, KodyNumber, KodyObject, KodyValue};
+
+fn number(x: i64) -> KodyObject {
+    KodyObject::from(KodyValue::Number(KodyNumber::from_int(x)))
+}
+
+#[test]
+fn registered_functions_keep_their_state() {
+    let mut interpreter = Interpreter::new();
+    let log = Rc::new(RefCell::new(vec![]));
+    let captured_log = log.clone();
+    interpreter.register_function("log", move |arguments| {
+        for argument in arguments {
+            captured_log.borrow_mut().push(argument.value.to_string());
+        }
+        Ok(KodyObject::new())
+    });
+    interpreter.register_function("fail", |_| Err(String::from("failed on purpose")));
+
+    interpreter
+        .eval("log(1, \"two\") xs = [3] xs.map(log)")
+        .unwrap();
+    assert_eq!(*log.borrow(), vec!["1", "two", "3"]);
+
+    let error = interpreter.eval("fail()").unwrap_err();
+    assert_eq!(error.code(), "E0205");
+    assert_eq!(error.message(), "failed on purpose");
+}
+
+#[test]
+fn globals_and_calls() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("limit", number(10));
+    assert_eq!(
+        interpreter.eval("func clamp(x) { if x > limit { return limit } return x } total = 5"),
+        Ok(KodyObject::new())
+    );
+    assert_eq!(interpreter.get_global("total"), Some(number(5)));
+    assert_eq!(interpreter.get_global("missing"), None);
+
+    assert_eq!(interpreter.call("clamp", vec![number(42)]), Ok(number(10)));
+    interpreter.set_global("limit", number(50));
+    assert_eq!(interpreter.call("clamp", vec![number(42)]), Ok(number(42)));
+    assert_eq!(interpreter.eval("clamp(99)"), Ok(number(50)));
+    assert_eq!(interpreter.eval("return total + 1 total"), Ok(number(6)));
+
+    // errors of the call itself have no place in the source
+    let error = interpreter.call("clamp", vec![]).unwrap_err();
+    assert_eq!((error.code(), error.span()), ("E0203", None));
+    let error = interpreter.call("nothing", vec![]).unwrap_err();
+    assert_eq!((error.code(), error.span()), ("E0201", None));
+    let error = interpreter.call("total", vec![]).unwrap_err();
+    assert_eq!((error.code(), error.span()), ("E0204", None));
+}
+
+#[test]
+fn errors_leave_the_session_usable() {
+    let mut interpreter = Interpreter::new();
+    assert!(interpreter.eval("if true { inner = 1 missing }").is_err());
+    assert!(interpreter.eval("x = ").is_err());
+    interpreter.eval("outer = 2").unwrap();
+    assert_eq!(interpreter.get_global("outer"), Some(number(2)));
+    assert_eq!(interpreter.get_global("inner"), None);
+}
+
+#[test]
+fn deep_recursion_is_an_error() {
+    // runs on the default stack of a test thread, which the interpreter
+    // grows when it runs short
+    let mut interpreter = Interpreter::new();
+    let error = interpreter
+        .eval("func f(n) { return f(n + 1) } f(0)")
+        .unwrap_err();
+    assert!(matches!(error, KodyError::Runtime(_)));
+    assert_eq!(error.code(), "E0209");
+    assert_eq!(error.message(), "Maximum recursion depth exceeded!");
+
+    let error = interpreter
+        .eval("func g(n) { return [n].map(func(x) g(x + 1)) } g(0)")
+        .unwrap_err();
+    assert_eq!(error.code(), "E0209");
+
+    // deep but limited recursion still works
+    interpreter
+        .eval("func down(n) { if n == 0 { return 0 } return down(n - 1) }")
+        .unwrap();
+    assert_eq!(interpreter.eval("down(900)"), Ok(number(0)));
+}
+
+#[test]
+fn typed_functions() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_typed("repeat", |text: String, times: usize| text.repeat(times));
+    interpreter.register_typed("total", |xs: Vec<i64>| xs.iter().sum::<i64>());
+    interpreter.register_typed("checked", |x: i64| {
+        if x < 0 {
+            Err(String::from("negative"))
+        } else {
+            Ok(x)
+        }
+    });
+    interpreter.register_typed("root", |x: f64| x.sqrt());
+    interpreter.register_typed("big", |x: u128| {
+        x.checked_mul(2).ok_or_else(|| String::from("too big"))
+    });
+
+    assert_eq!(
+        interpreter.eval("repeat(\"ab\", 3)"),
+        Ok("ababab".into_kody())
+    );
+    assert_eq!(interpreter.eval("total([1, 2, 3])"), Ok(6.into_kody()));
+    assert_eq!(
+        Vec::<String>::from_kody(&interpreter.eval("[\"a\", \"b\"]").unwrap()),
+        Ok(vec![String::from("a"), String::from("b")])
+    );
+
+    let error = interpreter.eval("repeat(\"ab\")").unwrap_err();
+    assert_eq!(error.code(), "E0205");
+    assert_eq!(
+        error.message(),
+        "repeat takes 2 argument(s) but 1 were given!"
+    );
+    let error = interpreter.eval("total([1, 2.5])").unwrap_err();
+    assert_eq!(
+        error.message(),
+        "Argument 1 of total: expected an integer between -9223372036854775808 and 9223372036854775807 but got 2.5!"
+    );
+    assert_eq!(
+        interpreter.eval("checked(-1)").unwrap_err().message(),
+        "negative"
+    );
+
+    // floats come back as approximate numbers
+    assert_eq!(interpreter.eval("root(2.25) == 1.5"), Ok(true.into_kody()));
+    assert_eq!(interpreter.eval("is_exact(root(4))"), Ok(false.into_kody()));
+    assert_eq!(
+        interpreter.eval("root(-1)").unwrap_err().message(),
+        "NaN is not a number!"
+    );
+    // integers past i128::MAX still fit in a u128
+    assert_eq!(
+        interpreter.eval("big(85070591730234615865843651857942052864)"),
+        interpreter.eval("170141183460469231731687303715884105728")
+    );
+    assert_eq!(
+        interpreter
+            .eval("big(170141183460469231731687303715884105728)")
+            .unwrap_err()
+            .message(),
+        "too big"
+    );
+}
+
+#[test]
+fn captured_output() {
+    let mut interpreter = Interpreter::new();
+    let output = Rc::new(RefCell::new(vec![]));
+    interpreter.set_output(output.clone());
+    interpreter
+        .eval("func greet(name) print(\"hello \", name) greet(\"kody\")")
+        .unwrap();
+    interpreter.call("greet", vec!["host".into_kody()]).unwrap();
+    assert_eq!(
+        String::from_utf8(output.take()).unwrap(),
+        "hello kody\nhello host\n"
+    );
+}